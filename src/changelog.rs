@@ -0,0 +1,274 @@
+use std::{
+    fmt::Display,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use typst_syntax::package::{PackageSpec, PackageVersion};
+
+use crate::{package, registry};
+
+/// File names a package may ship its changelog under, in lookup order
+const CHANGELOG_NAMES: &[&str] = &["CHANGELOG.md", "changelog.md", "CHANGES.md"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangelogSource {
+    /// Only look into the local Typst package cache
+    Cache,
+    /// Fall back to the release archive from the registry
+    Registry,
+}
+
+impl ValueEnum for ChangelogSource {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Cache, Self::Registry]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(match self {
+            Self::Cache => clap::builder::PossibleValue::new("cache"),
+            Self::Registry => clap::builder::PossibleValue::new("registry"),
+        })
+    }
+}
+
+impl Display for ChangelogSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Print the changelog entries between the versions of an upgrade
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub fn show(from: &PackageSpec, to: &PackageSpec, source: ChangelogSource) {
+    let Some(changelog) = load(&package::package_roots(), to, source) else {
+        warn!("Changelog": "No changelog found for {to}");
+        return;
+    };
+    let sections = sections(&changelog, from.version, to.version);
+    if sections.is_empty() {
+        warn!(
+            "Changelog": "No entries between {} and {} in the changelog of {to}",
+            from.version,
+            to.version,
+        );
+        return;
+    }
+    info!("Changelog": "{from} -> {}", to.version);
+    for line in sections.iter().flat_map(|section| section.lines()) {
+        diff!("{}", line);
+    }
+}
+
+/// Load the changelog shipped with `package`, looking into the local package cache first
+fn load(roots: &[PathBuf], package: &PackageSpec, source: ChangelogSource) -> Option<String> {
+    package::find_package_dir(roots, package)
+        .and_then(|dir| read_dir(&dir))
+        .or_else(|| match source {
+            ChangelogSource::Cache => None,
+            ChangelogSource::Registry => fetch(package),
+        })
+}
+
+fn read_dir(dir: &Path) -> Option<String> {
+    CHANGELOG_NAMES
+        .iter()
+        .find_map(|name| fs::read_to_string(dir.join(name)).ok())
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+fn fetch(package: &PackageSpec) -> Option<String> {
+    let url = format!(
+        "https://packages.typst.org/{}/{}-{}.tar.gz",
+        package.namespace, package.name, package.version,
+    );
+    let resp = registry::registry().download(&url).ok()?;
+    read_archive(resp)
+}
+
+/// Read the changelog at the root of a gzipped package archive
+fn read_archive(reader: impl Read) -> Option<String> {
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    for entry in archive.entries().ok()? {
+        let mut entry = entry.ok()?;
+        let path = entry.path().ok()?.into_owned();
+        let at_root = path.parent().is_some_and(|p| p.as_os_str().is_empty());
+        let is_changelog = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| CHANGELOG_NAMES.contains(&name));
+        if at_root && is_changelog {
+            let mut changelog = String::new();
+            entry.read_to_string(&mut changelog).ok()?;
+            return Some(changelog);
+        }
+    }
+    None
+}
+
+/// Extract the sections of a markdown changelog whose heading names a version in
+/// `(from, to]`
+///
+/// A section starts at a heading containing a version and lasts until the next heading
+/// of the same or a higher level.
+fn sections(changelog: &str, from: PackageVersion, to: PackageVersion) -> Vec<String> {
+    fn close(current: &mut Option<(usize, bool, Vec<&str>)>, result: &mut Vec<String>) {
+        if let Some((_, true, lines)) = current.take() {
+            result.push(lines.join("\n").trim_end().to_string());
+        }
+    }
+
+    let mut result = Vec::new();
+    let mut current: Option<(usize, bool, Vec<&str>)> = None;
+    for line in changelog.lines() {
+        if let Some(level) = heading_level(line) {
+            if current.as_ref().is_some_and(|(open, _, _)| level <= *open) {
+                close(&mut current, &mut result);
+            }
+            if current.is_none() {
+                if let Some(version) = heading_version(line) {
+                    current = Some((level, from < version && version <= to, vec![line]));
+                }
+                continue;
+            }
+        }
+        if let Some((_, _, lines)) = &mut current {
+            lines.push(line);
+        }
+    }
+    close(&mut current, &mut result);
+
+    result
+}
+
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    ((1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' '))).then_some(level)
+}
+
+fn heading_version(heading: &str) -> Option<PackageVersion> {
+    heading
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .find_map(|word| PackageVersion::from_str(word.trim_matches('.')).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path, str::FromStr};
+
+    use flate2::{Compression, write::GzEncoder};
+    use typst_syntax::package::{PackageSpec, PackageVersion};
+
+    use super::ChangelogSource;
+
+    fn changelog() -> String {
+        fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests")
+                .join("packages")
+                .join("preview")
+                .join("pack1")
+                .join("2.0.0")
+                .join("CHANGELOG.md"),
+        )
+        .unwrap()
+    }
+
+    fn version(s: &str) -> PackageVersion {
+        PackageVersion::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        for source in ["cache", "registry"] {
+            let choice: ChangelogSource = clap::ValueEnum::from_str(source, false).unwrap();
+            assert_eq!(choice.to_string(), source);
+        }
+    }
+
+    #[test]
+    fn sections() {
+        let changelog = changelog();
+
+        let sections = super::sections(&changelog, version("1.1.0"), version("2.0.0"));
+        assert_eq!(
+            sections,
+            [
+                "## [2.0.0] - 2024-06-01\n\n### Changed\n\n- `pack` is renamed to `pack-v2`",
+                "## [1.1.1] - 2024-05-20\n\n- Fix spacing of `pack`",
+            ]
+        );
+
+        let sections = super::sections(&changelog, version("0.2.1"), version("1.0.0"));
+        assert_eq!(
+            sections,
+            [
+                "## v1.0.0\n\n- First stable release",
+                "## 0.2.2\n\n- Fix typos"
+            ]
+        );
+
+        assert!(super::sections(&changelog, version("2.0.0"), version("3.0.0")).is_empty());
+    }
+
+    #[test]
+    fn heading() {
+        assert_eq!(super::heading_level("## 1.0.0"), Some(2));
+        assert_eq!(super::heading_level("#"), Some(1));
+        assert_eq!(super::heading_level("#import"), None);
+        assert_eq!(super::heading_level("####### 1.0.0"), None);
+        assert_eq!(super::heading_version("## Unreleased"), None);
+        assert_eq!(
+            super::heading_version("## [0.3.1] - 2024-01-02"),
+            Some(version("0.3.1"))
+        );
+        assert_eq!(super::heading_version("# v1.2.3."), Some(version("1.2.3")));
+    }
+
+    #[test]
+    fn load_from_cache() {
+        let roots = [Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("packages")];
+        let package = PackageSpec::from_str("@preview/pack1:2.0.0").unwrap();
+        assert_eq!(
+            super::load(&roots, &package, ChangelogSource::Cache).unwrap(),
+            changelog()
+        );
+
+        let package = PackageSpec::from_str("@preview/pack1:1.1.1").unwrap();
+        assert!(super::load(&roots, &package, ChangelogSource::Cache).is_none());
+    }
+
+    #[test]
+    fn read_archive() {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, content) in [
+            ("docs/CHANGELOG.md", "not this one"),
+            ("CHANGELOG.md", "## 0.1.0"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+
+        assert_eq!(
+            super::read_archive(archive.as_slice()).as_deref(),
+            Some("## 0.1.0")
+        );
+        assert!(super::read_archive([].as_slice()).is_none());
+    }
+}
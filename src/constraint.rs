@@ -0,0 +1,278 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use regex::Regex;
+use typst_syntax::package::{PackageSpec, PackageVersion, VersionBound};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// Bounds on the versions a package may be upgraded to, e.g. `cetz=>=0.2,<0.4`
+///
+/// The package is given as `@namespace/name`, or by its name alone in the preview
+/// namespace. Bounds may leave out the minor and patch versions, which then match any
+/// value, so `<=0.3` allows `0.3.5` and `=1` allows any `1.x.y`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    /// The package as `@namespace/name`
+    package: String,
+    bounds: Vec<(Op, VersionBound)>,
+}
+
+impl Constraint {
+    fn allows(&self, version: PackageVersion) -> bool {
+        self.bounds.iter().all(|(op, bound)| match op {
+            Op::Eq => version.matches_eq(bound),
+            Op::Gt => version.matches_gt(bound),
+            Op::Ge => version.matches_ge(bound),
+            Op::Lt => version.matches_lt(bound),
+            Op::Le => version.matches_le(bound),
+        })
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (package, requirement) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected `PACKAGE=BOUNDS`, found `{s}`"))?;
+        let package = package.trim();
+        if package.is_empty() {
+            return Err(format!("Missing package name in `{s}`"));
+        }
+        if package.contains(['<', '>']) {
+            return Err(format!(
+                "Expected `PACKAGE=BOUNDS`, found `{s}` (the bounds follow an `=`, as in `cetz=>=0.2`)"
+            ));
+        }
+        let (namespace, name) = match package.strip_prefix('@') {
+            Some(rest) => rest.split_once('/').unwrap_or((rest, "")),
+            None => ("preview", package),
+        };
+        if !typst_syntax::is_ident(namespace) || !typst_syntax::is_ident(name) {
+            return Err(format!(
+                "Expected a package like `@preview/cetz` or `cetz`, found `{package}`"
+            ));
+        }
+        let qualified = format!("@{namespace}/{name}");
+
+        let bounds = requirement
+            .split(',')
+            .map(|bound| {
+                let bound = bound.trim();
+                let (op, version) = [
+                    (">=", Op::Ge),
+                    ("<=", Op::Le),
+                    (">", Op::Gt),
+                    ("<", Op::Lt),
+                    ("=", Op::Eq),
+                ]
+                .into_iter()
+                .find_map(|(prefix, op)| bound.strip_prefix(prefix).map(|rest| (op, rest)))
+                .unwrap_or((Op::Eq, bound));
+                let version = VersionBound::from_str(version.trim())
+                    .map_err(|err| format!("Invalid bound `{bound}` of {package}: {err}"))?;
+                Ok((op, version))
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self {
+            package: qualified,
+            bounds,
+        })
+    }
+}
+
+/// A minimum time since publication, e.g. `7d`, `12h` or `2w`, in days without a unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Age(pub Duration);
+
+impl FromStr for Age {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+        let number = number
+            .parse::<u64>()
+            .map_err(|_| format!("Expected a number with a unit like `7d`, found `{s}`"))?;
+        let seconds = match unit {
+            "m" => 60,
+            "h" => 60 * 60,
+            "" | "d" => 24 * 60 * 60,
+            "w" => 7 * 24 * 60 * 60,
+            _ => {
+                return Err(format!(
+                    "Unknown unit `{unit}` of `{s}`, expected m, h, d or w"
+                ));
+            }
+        };
+        let seconds = number
+            .checked_mul(seconds)
+            .ok_or_else(|| format!("Age `{s}` is too long"))?;
+        Ok(Self(Duration::from_secs(seconds)))
+    }
+}
+
+/// Rules restricting which versions are candidates for upgrades
+#[derive(Debug, Default)]
+pub struct Constraints {
+    /// Patterns of versions never upgraded to, of any package
+    exclude: Vec<Regex>,
+    bounds: HashMap<String, Vec<Constraint>>,
+    /// Latest publication time of candidates, in seconds since the Unix epoch
+    published_before: Option<u64>,
+}
+
+impl Constraints {
+    pub fn new(exclude: Vec<Regex>, constraints: Vec<Constraint>) -> Self {
+        let mut bounds = HashMap::<_, Vec<_>>::new();
+        for constraint in constraints {
+            bounds
+                .entry(constraint.package.clone())
+                .or_default()
+                .push(constraint);
+        }
+        Self {
+            exclude,
+            bounds,
+            published_before: None,
+        }
+    }
+
+    /// Only allow versions published at least `age` before `now`
+    ///
+    /// Versions without a known publication time are allowed.
+    pub fn min_age(mut self, age: Age, now: SystemTime) -> Self {
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.published_before = Some(now.saturating_sub(age.0).as_secs());
+        self
+    }
+
+    /// Whether imports may be upgraded to `package`, published at `released` if known
+    pub fn allows(&self, package: &PackageSpec, released: Option<u64>) -> bool {
+        let version = package.version;
+        let text = version.to_string();
+        let old_enough = match (self.published_before, released) {
+            (Some(cutoff), Some(released)) => released <= cutoff,
+            _ => true,
+        };
+        old_enough
+            && !self.exclude.iter().any(|regex| regex.is_match(&text))
+            && self
+                .bounds
+                .get(&format!("@{}/{}", package.namespace, package.name))
+                .is_none_or(|bounds| bounds.iter().all(|bound| bound.allows(version)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        str::FromStr,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use regex::Regex;
+    use typst_syntax::package::PackageSpec;
+
+    use super::{Age, Constraint, Constraints};
+
+    fn spec(s: &str) -> PackageSpec {
+        PackageSpec::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert!(Constraint::from_str("cetz=>=0.2,<0.4").is_ok());
+        assert!(Constraint::from_str("cetz = 0.2.1").is_ok());
+        assert_eq!(
+            Constraint::from_str("@preview/cetz=0.2"),
+            Constraint::from_str("cetz=0.2")
+        );
+        assert_eq!(
+            Constraint::from_str("@preview=0.2").unwrap_err(),
+            "Expected a package like `@preview/cetz` or `cetz`, found `@preview`"
+        );
+        assert_eq!(
+            Constraint::from_str("cetz").unwrap_err(),
+            "Expected `PACKAGE=BOUNDS`, found `cetz`"
+        );
+        assert_eq!(
+            Constraint::from_str("=1.0").unwrap_err(),
+            "Missing package name in `=1.0`"
+        );
+        assert_eq!(
+            Constraint::from_str("cetz>=0.2").unwrap_err(),
+            "Expected `PACKAGE=BOUNDS`, found `cetz>=0.2` (the bounds follow an `=`, as in `cetz=>=0.2`)"
+        );
+        assert_eq!(
+            Constraint::from_str("ce tz=0.2").unwrap_err(),
+            "Expected a package like `@preview/cetz` or `cetz`, found `ce tz`"
+        );
+        assert!(
+            Constraint::from_str("cetz=>=0.x")
+                .unwrap_err()
+                .starts_with("Invalid bound `>=0.x` of cetz: ")
+        );
+    }
+
+    #[test]
+    fn allows() {
+        let constraints = Constraints::new(
+            vec![Regex::new(r"\.99$").unwrap()],
+            vec![
+                Constraint::from_str("cetz=>=0.2,<0.4").unwrap(),
+                Constraint::from_str("cetz=<=0.3").unwrap(),
+                Constraint::from_str("touying=1").unwrap(),
+                Constraint::from_str("@local/touying=2").unwrap(),
+            ],
+        );
+        assert!(!constraints.allows(&spec("@preview/cetz:0.1.9"), None));
+        assert!(constraints.allows(&spec("@preview/cetz:0.2.0"), None));
+        assert!(constraints.allows(&spec("@preview/cetz:0.3.5"), None));
+        assert!(!constraints.allows(&spec("@preview/cetz:0.3.99"), None));
+        assert!(!constraints.allows(&spec("@preview/cetz:0.4.0"), None));
+        assert!(constraints.allows(&spec("@preview/touying:1.2.0"), None));
+        assert!(!constraints.allows(&spec("@preview/touying:2.0.0"), None));
+        assert!(constraints.allows(&spec("@local/touying:2.0.0"), None));
+        assert!(constraints.allows(&spec("@preview/other:5.0.0"), None));
+        assert!(!constraints.allows(&spec("@preview/other:5.0.99"), None));
+    }
+
+    #[test]
+    fn min_age() {
+        assert_eq!(Age::from_str("7d"), Ok(Age(Duration::from_secs(7 * 86400))));
+        assert_eq!(Age::from_str("3"), Ok(Age(Duration::from_secs(3 * 86400))));
+        assert_eq!(
+            Age::from_str("12h"),
+            Ok(Age(Duration::from_secs(12 * 3600)))
+        );
+        assert_eq!(
+            Age::from_str("2y").unwrap_err(),
+            "Unknown unit `y` of `2y`, expected m, h, d or w"
+        );
+        assert!(Age::from_str("d").is_err());
+        assert_eq!(
+            Age::from_str("99999999999999999w").unwrap_err(),
+            "Age `99999999999999999w` is too long"
+        );
+
+        let now = UNIX_EPOCH + Duration::from_secs(30 * 86400);
+        let constraints = Constraints::default().min_age(Age::from_str("1w").unwrap(), now);
+        let cetz = spec("@preview/cetz:1.0.0");
+        assert!(constraints.allows(&cetz, Some(23 * 86400)));
+        assert!(!constraints.allows(&cetz, Some(23 * 86400 + 1)));
+        assert!(constraints.allows(&cetz, None));
+    }
+}
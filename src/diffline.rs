@@ -1,23 +1,30 @@
-use std::{fmt::Display, sync::OnceLock};
+use std::{fmt::Display, io::Write, sync::OnceLock};
 
 use clap::ValueEnum;
+use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Fallback terminal width when the real one cannot be queried
+const DEFAULT_WIDTH: usize = 120;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DiffChoice {
     Short,
     Full,
+    SideBySide,
     None,
 }
 
 impl ValueEnum for DiffChoice {
     fn value_variants<'a>() -> &'a [Self] {
-        &[Self::Short, Self::Full, Self::None]
+        &[Self::Short, Self::Full, Self::SideBySide, Self::None]
     }
 
     fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
         Some(match self {
             Self::Short => clap::builder::PossibleValue::new("short"),
             Self::Full => clap::builder::PossibleValue::new("full"),
+            Self::SideBySide => clap::builder::PossibleValue::new("side-by-side"),
             Self::None => clap::builder::PossibleValue::new("none"),
         })
     }
@@ -40,7 +47,19 @@ pub fn init(diff: DiffChoice) {
 
 #[cfg_attr(coverage_nightly, coverage(off))]
 pub fn show(old: &str, new: &str) {
-    show_difflines(old, new, *DIFF_CHOICE.get().unwrap(), |res| match res {
+    let mut diff = *DIFF_CHOICE.get().unwrap();
+    if diff == DiffChoice::SideBySide {
+        if crate::term::stdout_is_terminal() {
+            let width = terminal_size::terminal_size()
+                .map(|(w, _)| w.0 as usize)
+                .unwrap_or(DEFAULT_WIDTH);
+            show_side_by_side(old, new, width).expect("Cannot write to stdout");
+            return;
+        }
+        // Two columns make no sense without a terminal, e.g. when piped into a file
+        diff = DiffChoice::Short;
+    }
+    show_difflines(old, new, diff, |res| match res {
         diff::Result::Left(l) => {
             diff!(del "{}", l);
         }
@@ -64,6 +83,114 @@ fn show_difflines(old: &str, new: &str, diff: DiffChoice, out: impl Fn(diff::Res
     }
 }
 
+/// A row of the side-by-side view, holding the 1-based line number and text of each side
+#[derive(Debug, PartialEq, Eq)]
+struct Row<'a> {
+    left: Option<(usize, &'a str)>,
+    right: Option<(usize, &'a str)>,
+}
+
+/// Pair the changed lines of `old` and `new` into rows, deletions on the left and
+/// additions on the right
+fn side_by_side_rows<'a>(old: &'a str, new: &'a str) -> Vec<Row<'a>> {
+    fn flush<'a>(
+        rows: &mut Vec<Row<'a>>,
+        dels: &mut Vec<(usize, &'a str)>,
+        adds: &mut Vec<(usize, &'a str)>,
+    ) {
+        for i in 0..dels.len().max(adds.len()) {
+            rows.push(Row {
+                left: dels.get(i).copied(),
+                right: adds.get(i).copied(),
+            });
+        }
+        dels.clear();
+        adds.clear();
+    }
+
+    let (mut rows, mut dels, mut adds) = (Vec::new(), Vec::new(), Vec::new());
+    let (mut left_no, mut right_no) = (0, 0);
+    for line in diff::lines(old, new) {
+        match line {
+            diff::Result::Left(l) => {
+                left_no += 1;
+                dels.push((left_no, l));
+            }
+            diff::Result::Right(r) => {
+                right_no += 1;
+                adds.push((right_no, r));
+            }
+            diff::Result::Both(_, _) => {
+                left_no += 1;
+                right_no += 1;
+                flush(&mut rows, &mut dels, &mut adds);
+            }
+        }
+    }
+    flush(&mut rows, &mut dels, &mut adds);
+    rows
+}
+
+/// Expand tabs, then pad or truncate `text` to exactly `width` terminal columns
+fn fit(text: &str, width: usize) -> String {
+    let text = text.replace('\t', "    ");
+    let text_width = text.width();
+    if text_width <= width {
+        return format!("{text}{}", " ".repeat(width - text_width));
+    }
+
+    // Leave a column for the ellipsis, and pad if a wide character does not fit exactly
+    let mut result = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let c_width = c.width().unwrap_or(0);
+        if used + c_width > width.saturating_sub(1) {
+            break;
+        }
+        result.push(c);
+        used += c_width;
+    }
+    result.push('…');
+    result.push_str(&" ".repeat(width.saturating_sub(used + 1)));
+    result
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+fn show_side_by_side(old: &str, new: &str, width: usize) -> Result<(), std::io::Error> {
+    fn cell(
+        stream: &mut StandardStream,
+        side: Option<(usize, &str)>,
+        color: Color,
+        column: usize,
+    ) -> Result<(), std::io::Error> {
+        let Some((no, text)) = side else {
+            return write!(stream, "{:>4} {}", "", fit("", column));
+        };
+        stream.set_color(ColorSpec::new().set_dimmed(true))?;
+        write!(stream, "{no:>4} ")?;
+        stream.set_color(ColorSpec::new().set_fg(Some(color)))?;
+        write!(stream, "{}", fit(text, column))?;
+        stream.reset()
+    }
+
+    // Written directly rather than with `diff!`, so at the same level
+    if !crate::term::enabled(crate::term::Level::Normal) {
+        return Ok(());
+    }
+    // Leave room for the motion column, two line numbers and the separator
+    let column = (width.saturating_sub(13 + 2 * 5 + 3) / 2).max(10);
+    let mut stream = crate::term::stdout();
+
+    for row in side_by_side_rows(old, new) {
+        write!(&mut stream, "{:>12} ", "")?;
+        cell(&mut stream, row.left, Color::Red, column)?;
+        write!(&mut stream, " │ ")?;
+        cell(&mut stream, row.right, Color::Green, column)?;
+        writeln!(&mut stream)?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use clap::ValueEnum;
@@ -75,13 +202,18 @@ mod test {
         let variants: &[DiffChoice] = DiffChoice::value_variants();
         assert_eq!(
             variants,
-            &[DiffChoice::Short, DiffChoice::Full, DiffChoice::None]
+            &[
+                DiffChoice::Short,
+                DiffChoice::Full,
+                DiffChoice::SideBySide,
+                DiffChoice::None
+            ]
         );
     }
 
     #[test]
     fn parse() {
-        for diff in ["short", "full", "none"] {
+        for diff in ["short", "full", "side-by-side", "none"] {
             let choice: DiffChoice = clap::ValueEnum::from_str(diff, false).unwrap();
             assert_eq!(choice.to_string(), diff);
         }
@@ -120,4 +252,39 @@ mod test {
             diff::Result::Right(r) => assert_eq!(r, "line4"),
         });
     }
+
+    #[test]
+    fn side_by_side_rows() {
+        let old = "line1\nline2\nline3\nline4";
+        let new = "line1\nline2a\nline2b\nline3\nline5";
+        let rows = super::side_by_side_rows(old, new);
+        assert_eq!(
+            rows,
+            vec![
+                super::Row {
+                    left: Some((2, "line2")),
+                    right: Some((2, "line2a")),
+                },
+                super::Row {
+                    left: None,
+                    right: Some((3, "line2b")),
+                },
+                super::Row {
+                    left: Some((4, "line4")),
+                    right: Some((5, "line5")),
+                },
+            ]
+        );
+        assert!(super::side_by_side_rows(old, old).is_empty());
+    }
+
+    #[test]
+    fn fit() {
+        assert_eq!(super::fit("abc", 5), "abc  ");
+        assert_eq!(super::fit("abcdef", 5), "abcd…");
+        assert_eq!(super::fit("\tx", 6), "    x ");
+        assert_eq!(super::fit("ｌｉｂ4", 4), "ｌ… ");
+        assert_eq!(super::fit("ｌｉｂ4", 7), "ｌｉｂ4");
+        assert_eq!(super::fit("ｌｉ", 6), "ｌｉ  ");
+    }
 }
@@ -0,0 +1,106 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use typst_syntax::{
+    ast::{Expr, Imports, Markup},
+    package::PackageManifest,
+};
+
+/// Compute the top-level names exported by the entrypoint of the package in `dir`
+///
+/// Returns `None` if the exports cannot be determined statically, e.g. when the
+/// entrypoint re-exports everything from another package.
+pub fn package_exports(dir: &Path) -> Option<HashSet<String>> {
+    let manifest = fs::read_to_string(dir.join("typst.toml")).ok()?;
+    let manifest: PackageManifest = toml::from_str(&manifest).ok()?;
+    module_exports(
+        dir,
+        &dir.join(manifest.package.entrypoint.as_str()),
+        &mut HashSet::new(),
+    )
+}
+
+fn module_exports(
+    root: &Path,
+    path: &Path,
+    visited: &mut HashSet<PathBuf>,
+) -> Option<HashSet<String>> {
+    if !visited.insert(path.to_path_buf()) {
+        // Names of cyclic wildcard imports are already collected by the importer
+        return Some(HashSet::new());
+    }
+
+    let tree = typst_syntax::parse(&fs::read_to_string(path).ok()?);
+    let mut names = HashSet::new();
+    for expr in tree.cast::<Markup>()?.exprs() {
+        match expr {
+            Expr::LetBinding(binding) => names.extend(
+                binding
+                    .kind()
+                    .bindings()
+                    .into_iter()
+                    .map(|ident| ident.get().to_string()),
+            ),
+            Expr::ModuleImport(import) => {
+                if let Some(name) = import.new_name() {
+                    names.insert(name.get().to_string());
+                }
+                match import.imports() {
+                    None if import.new_name().is_none() => {
+                        names.insert(import.bare_name().ok()?.to_string());
+                    }
+                    None => (),
+                    Some(Imports::Items(items)) => {
+                        names.extend(items.iter().map(|item| item.bound_name().get().to_string()))
+                    }
+                    Some(Imports::Wildcard) => {
+                        let Expr::Str(source) = import.source() else {
+                            return None;
+                        };
+                        let source = source.get();
+                        let target = if source.starts_with('@') {
+                            return None;
+                        } else if let Some(absolute) = source.strip_prefix('/') {
+                            root.join(absolute)
+                        } else {
+                            path.parent()?.join(source.as_str())
+                        };
+                        names.extend(module_exports(root, &target, visited)?);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Some(names)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, path::Path};
+
+    #[test]
+    fn package_exports() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("packages")
+            .join("preview")
+            .join("pack1")
+            .join("2.0.0");
+        assert_eq!(
+            super::package_exports(&dir).unwrap(),
+            HashSet::from(
+                [
+                    "pack-v2", "helper", "draw", "round", "rect", "major", "minor"
+                ]
+                .map(String::from)
+            )
+        );
+
+        assert!(super::package_exports(&dir.join("src")).is_none());
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+const BOM: char = '\u{feff}';
+
+/// Decoded content of a source file, remembering how to encode it back
+#[derive(Debug)]
+pub struct SourceText {
+    /// The content without byte order mark
+    pub text: String,
+    bom: bool,
+    crlf: bool,
+}
+
+impl SourceText {
+    /// Decode UTF-8 `bytes`, noting a leading byte order mark and CRLF line endings
+    pub fn decode(bytes: Vec<u8>) -> Result<Self, String> {
+        let text = String::from_utf8(bytes)
+            .map_err(|err| format!("Invalid UTF-8 at byte {}", err.utf8_error().valid_up_to()))?;
+        let (bom, text) = match text.strip_prefix(BOM) {
+            Some(text) => (true, text.to_string()),
+            None => (false, text),
+        };
+        // Only files consistently using CRLF are normalized when written back
+        let crlf =
+            text.contains("\r\n") && text.matches('\n').count() == text.matches("\r\n").count();
+        Ok(Self { text, bom, crlf })
+    }
+
+    /// Encode `text`, derived from this source, with the same byte order mark and line
+    /// endings
+    pub fn encode(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len() + BOM.len_utf8());
+        if self.bom {
+            result.push(BOM);
+        }
+        let mut prev = None;
+        for c in text.chars() {
+            if self.crlf && c == '\n' && prev != Some('\r') {
+                result.push('\r');
+            }
+            result.push(c);
+            prev = Some(c);
+        }
+        result
+    }
+}
+
+/// Read and decode the source file at `path`
+pub fn read_source(path: &Path) -> Result<SourceText, String> {
+    let bytes = fs::read(path).map_err(|err| format!("Cannot read file: {err}"))?;
+    SourceText::decode(bytes)
+}
+
+/// Replace the content of `path` without ever leaving it half-written
+///
+/// The content goes to a temporary file next to the real target of `path` (following
+/// symlinks), which is then renamed over the target with the original permissions. With
+/// `backup`, a copy of the original is kept under the target's name plus that suffix.
+pub fn write_atomic(path: &Path, content: &[u8], backup: Option<&str>) -> io::Result<()> {
+    let target = fs::canonicalize(path)?;
+    let dir = target
+        .parent()
+        .ok_or_else(|| io::Error::other("Cannot write to the root directory"))?;
+    let permissions = fs::metadata(&target)?.permissions();
+
+    if let Some(suffix) = backup {
+        let mut backup = target.clone().into_os_string();
+        backup.push(suffix);
+        fs::copy(&target, backup)?;
+    }
+
+    let mut temp = tempfile::Builder::new()
+        .prefix(".typst-upgrade")
+        .tempfile_in(dir)?;
+    temp.write_all(content)?;
+    temp.as_file().sync_all()?;
+    fs::set_permissions(temp.path(), permissions)?;
+    temp.persist(&target).map_err(|err| err.error)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::SourceText;
+
+    #[test]
+    fn decode_and_encode() {
+        let source = SourceText::decode(b"\xef\xbb\xbf#import\r\n\r\nx\r\n".to_vec()).unwrap();
+        assert_eq!(source.text, "#import\r\n\r\nx\r\n");
+        assert_eq!(
+            source.encode("#import\r\ny\nz"),
+            "\u{feff}#import\r\ny\r\nz"
+        );
+
+        let source = SourceText::decode(b"a\r\nb\n".to_vec()).unwrap();
+        assert_eq!(source.text, "a\r\nb\n");
+        assert_eq!(source.encode("a\r\nc\n"), "a\r\nc\n");
+
+        let source = SourceText::decode(b"a\nb".to_vec()).unwrap();
+        assert_eq!(source.encode("a\nc"), "a\nc");
+
+        assert_eq!(
+            SourceText::decode(b"ok\xff".to_vec()).unwrap_err(),
+            "Invalid UTF-8 at byte 2"
+        );
+    }
+
+    #[test]
+    fn read_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.typ");
+        fs::write(&path, "\u{feff}text").unwrap();
+        assert_eq!(super::read_source(&path).unwrap().text, "text");
+        assert!(super::read_source(&dir.path().join("missing.typ")).is_err());
+    }
+
+    #[test]
+    fn write_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.typ");
+        fs::write(&path, "old").unwrap();
+
+        super::write_atomic(&path, b"new", None).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        super::write_atomic(&path, b"newer", Some(".bak")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "newer");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("main.typ.bak")).unwrap(),
+            "new"
+        );
+
+        assert!(super::write_atomic(&dir.path().join("missing.typ"), b"", None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic_through_symlink() {
+        use std::os::unix::fs::{PermissionsExt, symlink};
+
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("lib").join("main.typ");
+        let link = dir.path().join("main.typ");
+        fs::create_dir(dir.path().join("lib")).unwrap();
+        fs::write(&target, "old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o640)).unwrap();
+        symlink(&target, &link).unwrap();
+
+        super::write_atomic(&link, b"new", Some("~")).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        assert_eq!(
+            fs::metadata(&target).unwrap().permissions().mode() & 0o777,
+            0o640
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lib").join("main.typ~")).unwrap(),
+            "old"
+        );
+    }
+}
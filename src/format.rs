@@ -0,0 +1,162 @@
+use std::{fmt::Display, path::Path};
+
+use clap::ValueEnum;
+
+use crate::upgrade::Report;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Github,
+    Sarif,
+    Junit,
+}
+
+impl ValueEnum for OutputFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Text, Self::Github, Self::Sarif, Self::Junit]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(match self {
+            Self::Text => clap::builder::PossibleValue::new("text"),
+            Self::Github => clap::builder::PossibleValue::new("github"),
+            Self::Sarif => clap::builder::PossibleValue::new("sarif"),
+            Self::Junit => clap::builder::PossibleValue::new("junit"),
+        })
+    }
+}
+
+impl OutputFormat {
+    /// Whether the format is a report printed to stdout, leaving the text output to stderr
+    pub fn is_report(self) -> bool {
+        matches!(self, Self::Sarif | Self::Junit)
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// 1-based line and column (in characters) of the byte `offset` in `text`
+pub fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// GitHub Actions workflow commands annotating the outdated and unknown imports in `file`
+pub fn github(file: &Path, text: &str, report: &Report) -> Vec<String> {
+    fn escape_data(s: &str) -> String {
+        s.replace('%', "%25")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A")
+    }
+
+    fn escape_property(s: &str) -> String {
+        escape_data(s).replace(':', "%3A").replace(',', "%2C")
+    }
+
+    let file = file.to_string_lossy();
+    let file = escape_property(file.strip_prefix("./").unwrap_or(&file));
+    let annotate = |level: &str, start: usize, message: String| {
+        let (line, col) = line_col(text, start);
+        (
+            start,
+            format!(
+                "::{level} file={file},line={line},col={col}::{}",
+                escape_data(&message),
+            ),
+        )
+    };
+
+    let mut result = report
+        .upgrades
+        .iter()
+        .map(|upgrade| annotate("warning", upgrade.range.start, upgrade.describe()))
+        .chain(report.held_back.iter().map(|upgrade| {
+            let message = format!("{} (incompatible)", upgrade.describe());
+            annotate("warning", upgrade.range.start, message)
+        }))
+        .chain(report.refused.iter().map(|upgrade| {
+            let message = format!("{} (would break imported items)", upgrade.describe());
+            annotate("warning", upgrade.range.start, message)
+        }))
+        .chain(
+            report
+                .issues
+                .iter()
+                .map(|issue| annotate("error", issue.range.start, issue.describe())),
+        )
+        .collect::<Vec<_>>();
+    result.sort_by_key(|(start, _)| *start);
+    result.into_iter().map(|(_, command)| command).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, str::FromStr};
+
+    use typst_syntax::package::PackageSpec;
+
+    use crate::upgrade::{Issue, IssueKind, Report, Upgrade};
+
+    use super::OutputFormat;
+
+    #[test]
+    fn parse() {
+        for format in ["text", "github", "sarif", "junit"] {
+            let choice: OutputFormat = clap::ValueEnum::from_str(format, false).unwrap();
+            assert_eq!(choice.to_string(), format);
+        }
+    }
+
+    #[test]
+    fn line_col() {
+        let text = "a\nｌｉｂ = 1\r\nx";
+        assert_eq!(super::line_col(text, 0), (1, 1));
+        assert_eq!(super::line_col(text, 2), (2, 1));
+        assert_eq!(super::line_col(text, text.find('=').unwrap()), (2, 5));
+        assert_eq!(super::line_col(text, text.len()), (3, 2));
+    }
+
+    #[test]
+    fn github() {
+        let text = "#import \"@preview/pack1:1.0.0\"\n  #import \"@preview/pack2:1.0.0\": a\n#import \"@preview/pack4:1.0.0\"";
+        let report = Report {
+            has_incompat_versions: true,
+            upgrades: vec![
+                Upgrade::new(text, "@preview/pack4:1.0.0", "@preview/pack3:3.0.0"),
+                Upgrade::new(text, "@preview/pack1:1.0.0", "@preview/pack1:1.1.0"),
+            ],
+            held_back: vec![Upgrade::new(
+                text,
+                "@preview/pack2:1.0.0",
+                "@preview/pack2:2.0.0",
+            )],
+            refused: Vec::new(),
+            issues: vec![Issue {
+                range: 1..2,
+                package: PackageSpec::from_str("@other/pack5:1.0.0").unwrap(),
+                kind: IssueKind::UnknownNamespace,
+            }],
+        };
+        assert_eq!(
+            super::github(Path::new("./dir,1/main.typ"), text, &report),
+            [
+                "::error file=dir%2C1/main.typ,line=1,col=2::Unknown namespace other of @other/pack5:1.0.0",
+                "::warning file=dir%2C1/main.typ,line=1,col=9::@preview/pack1 1.0.0 can be upgraded to 1.1.0",
+                "::warning file=dir%2C1/main.typ,line=2,col=11::@preview/pack2 1.0.0 can be upgraded to 2.0.0 (incompatible)",
+                "::warning file=dir%2C1/main.typ,line=3,col=9::@preview/pack4 1.0.0 can be replaced by @preview/pack3:3.0.0",
+            ]
+        );
+    }
+}
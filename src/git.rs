@@ -0,0 +1,374 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    file::{self, SourceText},
+    migrate::Migrations,
+    term,
+    upgrade::Upgrade,
+};
+
+/// A file with the upgrades found in it, not yet written
+pub struct PendingFile {
+    pub path: PathBuf,
+    /// The original content of the file
+    pub source: SourceText,
+    pub upgrades: Vec<Upgrade>,
+}
+
+/// A commit upgrading a single package
+#[derive(Debug, PartialEq, Eq)]
+struct Commit {
+    message: String,
+    /// Affected files with their content after this and all preceding commits
+    files: Vec<(PathBuf, String)>,
+}
+
+/// Run `git` in `dir`, returning its trimmed standard output
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|err| format!("Cannot run git: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The top-level directory of the working tree containing `path`, if any
+pub fn toplevel(path: &Path) -> Option<PathBuf> {
+    let dir = if path.is_dir() { path } else { path.parent()? };
+    // An empty parent means the current directory
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    git(dir, &["rev-parse", "--show-toplevel"])
+        .ok()
+        .map(PathBuf::from)
+}
+
+/// Whether the working tree at `repo` has no uncommitted changes or untracked files
+pub fn is_clean(repo: &Path) -> Result<bool, String> {
+    git(repo, &["status", "--porcelain"]).map(|status| status.is_empty())
+}
+
+/// Whether `path` is tracked and has no uncommitted changes
+///
+/// Files outside any working tree are considered clean.
+pub fn is_file_clean(path: &Path) -> bool {
+    let Ok(path) = fs::canonicalize(path) else {
+        return true;
+    };
+    let (Some(repo), Some(path)) = (toplevel(&path), path.to_str()) else {
+        return true;
+    };
+    git(&repo, &["status", "--porcelain", "--", path]).is_ok_and(|status| status.is_empty())
+}
+
+/// Write the upgrades of `files` and commit them package by package
+///
+/// Each commit only touches the files importing the upgraded package, and includes the
+/// `migrations` of that package.
+pub fn commit_by_package(files: &[PendingFile], migrations: &Migrations) -> Result<(), String> {
+    for commit in plan(files, migrations) {
+        let mut repos = BTreeMap::<PathBuf, Vec<&Path>>::new();
+        for (path, content) in &commit.files {
+            file::write_atomic(path, content.as_bytes(), None)
+                .map_err(|err| format!("Cannot write {}: {err}", path.display()))?;
+            let repo = toplevel(path)
+                .ok_or_else(|| format!("{} is not in a git repository", path.display()))?;
+            repos.entry(repo).or_default().push(path);
+        }
+        for (repo, paths) in repos {
+            let paths = paths
+                .iter()
+                .map(|path| fs::canonicalize(path).map_err(|err| err.to_string()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let paths = paths
+                .iter()
+                .map(|path| path.to_str().ok_or("Non UTF-8 path"))
+                .collect::<Result<Vec<_>, _>>()?;
+            git(&repo, &[&["add", "--"][..], &paths].concat())?;
+            git(
+                &repo,
+                &[&["commit", "-q", "-m", &commit.message, "--"][..], &paths].concat(),
+            )?;
+            info!("Committed": "{}", commit.message);
+        }
+    }
+    Ok(())
+}
+
+/// Group the upgrades of `files` into one commit per package, ordered by package
+fn plan(files: &[PendingFile], migrations: &Migrations) -> Vec<Commit> {
+    let mut packages = BTreeMap::<_, Vec<&Upgrade>>::new();
+    for upgrade in files.iter().flat_map(|file| &file.upgrades) {
+        let key = (
+            format!("@{}/{}", upgrade.from.namespace, upgrade.from.name),
+            format!("@{}/{}", upgrade.to.namespace, upgrade.to.name),
+        );
+        packages.entry(key).or_default().push(upgrade);
+    }
+
+    let mut applied = Vec::new();
+    packages
+        .into_values()
+        .map(|upgrades| {
+            applied.extend(upgrades.iter().copied());
+            let files = files
+                .iter()
+                .filter(|file| {
+                    file.upgrades
+                        .iter()
+                        .any(|upgrade| upgrades.iter().any(|u| std::ptr::eq(*u, upgrade)))
+                })
+                .map(|file| {
+                    let upgrades = file
+                        .upgrades
+                        .iter()
+                        .filter(|upgrade| applied.iter().any(|u| std::ptr::eq(*u, *upgrade)))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    let text = apply(&file.source.text, upgrades.iter());
+                    // Migrations were reported when the files were upgraded
+                    let text =
+                        term::silenced(|| migrations.apply(&typst_syntax::parse(&text), &upgrades))
+                            .into_text();
+                    (file.path.clone(), file.source.encode(&text))
+                })
+                .collect();
+            Commit {
+                message: commit_message(&upgrades),
+                files,
+            }
+        })
+        .collect()
+}
+
+/// Describe upgrades of one package, e.g. `Upgrade @preview/cetz 0.2.1 -> 0.3.1`
+fn commit_message(upgrades: &[&Upgrade]) -> String {
+    fn join(items: impl Iterator<Item = String>) -> String {
+        items.collect::<Vec<_>>().join(", ")
+    }
+
+    let mut versions = upgrades
+        .iter()
+        .map(|upgrade| (upgrade.from.version, upgrade.to.version))
+        .collect::<Vec<_>>();
+    versions.sort_unstable();
+    versions.dedup();
+
+    let (from, to) = (&upgrades[0].from, &upgrades[0].to);
+    let package = format!("@{}/{}", from.namespace, from.name);
+    let old = join(versions.iter().map(|(old, _)| old.to_string()));
+    if from.name != to.name {
+        let new = join(
+            versions
+                .iter()
+                .map(|(_, new)| format!("@{}/{}:{new}", to.namespace, to.name)),
+        );
+        format!("Replace {package} {old} with {new}")
+    } else if versions.iter().all(|(_, new)| *new == versions[0].1) {
+        format!("Upgrade {package} {old} -> {}", versions[0].1)
+    } else {
+        let pairs = join(versions.iter().map(|(old, new)| format!("{old} -> {new}")));
+        format!("Upgrade {package} {pairs}")
+    }
+}
+
+/// Rewrite the import strings of `upgrades` in `text`
+fn apply<'a>(text: &str, upgrades: impl Iterator<Item = &'a Upgrade>) -> String {
+    let mut upgrades = upgrades.collect::<Vec<_>>();
+    upgrades.sort_by_key(|upgrade| upgrade.range.start);
+
+    let mut result = text.to_string();
+    for upgrade in upgrades.into_iter().rev() {
+        result.replace_range(upgrade.range.clone(), &format!("\"{}\"", upgrade.to));
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path, process::Command};
+
+    use crate::{file::SourceText, migrate::Migrations, upgrade::Upgrade};
+
+    use super::{Commit, PendingFile};
+
+    fn pending(path: &Path, text: &str, upgrades: &[(&str, &str)]) -> PendingFile {
+        PendingFile {
+            path: path.to_path_buf(),
+            source: SourceText::decode(text.into()).unwrap(),
+            upgrades: upgrades
+                .iter()
+                .map(|(from, to)| Upgrade::new(text, from, to))
+                .collect(),
+        }
+    }
+
+    const MAIN: &str = "#import \"@preview/pack2:1.0.0\"\n#import \"@preview/pack1:1.1.0\"\n";
+    const LIB: &str = "#import \"@preview/pack1:1.0.0\": pack\n#import \"@preview/pack4:1.0.0\"\n";
+
+    fn files(dir: &Path) -> Vec<PendingFile> {
+        vec![
+            pending(
+                &dir.join("main.typ"),
+                MAIN,
+                &[
+                    ("@preview/pack2:1.0.0", "@preview/pack2:2.0.0"),
+                    ("@preview/pack1:1.1.0", "@preview/pack1:2.0.0"),
+                ],
+            ),
+            pending(
+                &dir.join("lib.typ"),
+                LIB,
+                &[
+                    ("@preview/pack1:1.0.0", "@preview/pack1:2.0.0"),
+                    ("@preview/pack4:1.0.0", "@preview/pack3:3.0.0"),
+                ],
+            ),
+        ]
+    }
+
+    #[test]
+    fn plan() {
+        let dir = Path::new("proj");
+        assert_eq!(
+            super::plan(&files(dir), &Migrations::default()),
+            [
+                Commit {
+                    message: "Upgrade @preview/pack1 1.0.0, 1.1.0 -> 2.0.0".into(),
+                    files: vec![
+                        (
+                            dir.join("main.typ"),
+                            "#import \"@preview/pack2:1.0.0\"\n#import \"@preview/pack1:2.0.0\"\n"
+                                .into()
+                        ),
+                        (
+                            dir.join("lib.typ"),
+                            "#import \"@preview/pack1:2.0.0\": pack\n#import \"@preview/pack4:1.0.0\"\n"
+                                .into()
+                        ),
+                    ],
+                },
+                Commit {
+                    message: "Upgrade @preview/pack2 1.0.0 -> 2.0.0".into(),
+                    files: vec![(
+                        dir.join("main.typ"),
+                        "#import \"@preview/pack2:2.0.0\"\n#import \"@preview/pack1:2.0.0\"\n"
+                            .into()
+                    )],
+                },
+                Commit {
+                    message: "Replace @preview/pack4 1.0.0 with @preview/pack3:3.0.0".into(),
+                    files: vec![(
+                        dir.join("lib.typ"),
+                        "#import \"@preview/pack1:2.0.0\": pack\n#import \"@preview/pack3:3.0.0\"\n"
+                            .into()
+                    )],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_with_migrations() {
+        let mut migrations = Migrations::default();
+        migrations
+            .extend_from_str(
+                "[[migration]]\npackage = \"pack1\"\nsince = \"2.0.0\"\nrename = { pack = \"pack-v2\" }",
+            )
+            .unwrap();
+        let plan = super::plan(&files(Path::new("proj")), &migrations);
+        let lib = |commit: &Commit| commit.files.last().unwrap().1.clone();
+        assert_eq!(
+            lib(&plan[0]),
+            "#import \"@preview/pack1:2.0.0\": pack-v2\n#import \"@preview/pack4:1.0.0\"\n"
+        );
+        assert_eq!(
+            lib(&plan[2]),
+            "#import \"@preview/pack1:2.0.0\": pack-v2\n#import \"@preview/pack3:3.0.0\"\n"
+        );
+    }
+
+    #[test]
+    fn commit_message() {
+        let text = "\"@preview/pack1:0.1.0\" \"@preview/pack1:1.0.0\"";
+        let upgrades = [
+            Upgrade::new(text, "@preview/pack1:0.1.0", "@preview/pack1:0.1.1"),
+            Upgrade::new(text, "@preview/pack1:1.0.0", "@preview/pack1:1.1.0"),
+        ];
+        assert_eq!(
+            super::commit_message(&upgrades.iter().collect::<Vec<_>>()),
+            "Upgrade @preview/pack1 0.1.0 -> 0.1.1, 1.0.0 -> 1.1.0"
+        );
+    }
+
+    #[test]
+    fn commit_by_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        git(&["config", "user.name", "typst-upgrade"]);
+        git(&["config", "user.email", "typst-upgrade@example.com"]);
+        fs::write(dir.path().join("main.typ"), MAIN).unwrap();
+        fs::write(dir.path().join("lib.typ"), LIB).unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "Initial commit"]);
+
+        let repo = super::toplevel(&dir.path().join("main.typ")).unwrap();
+        assert_eq!(
+            fs::canonicalize(&repo).unwrap(),
+            fs::canonicalize(dir.path()).unwrap()
+        );
+        assert!(super::is_clean(&repo).unwrap());
+
+        super::commit_by_package(&files(dir.path()), &Migrations::default()).unwrap();
+        assert!(super::is_clean(&repo).unwrap());
+
+        let log = super::git(&repo, &["log", "--format=%s", "--name-only"]).unwrap();
+        assert_eq!(
+            log,
+            "Replace @preview/pack4 1.0.0 with @preview/pack3:3.0.0\n\nlib.typ\n\
+             Upgrade @preview/pack2 1.0.0 -> 2.0.0\n\nmain.typ\n\
+             Upgrade @preview/pack1 1.0.0, 1.1.0 -> 2.0.0\n\nlib.typ\nmain.typ\n\
+             Initial commit\n\nlib.typ\nmain.typ"
+        );
+
+        assert!(super::is_file_clean(&dir.path().join("main.typ")));
+        fs::write(dir.path().join("main.typ"), "").unwrap();
+        assert!(!super::is_file_clean(&dir.path().join("main.typ")));
+
+        fs::write(dir.path().join("new.typ"), "").unwrap();
+        assert!(!super::is_clean(&repo).unwrap());
+        assert!(!super::is_file_clean(&dir.path().join("new.typ")));
+
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("main.typ"), "").unwrap();
+        assert!(super::is_file_clean(&outside.path().join("main.typ")));
+
+        assert!(super::toplevel(Path::new("/")).is_none());
+    }
+}
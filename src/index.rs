@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Deserializer, de::DeserializeOwned};
+use serde_json::Value;
+use typst_syntax::package::{PackageVersion, VersionBound};
+
+use crate::registry;
+
+/// The index of the preview namespace, fetched once per run
+///
+/// Returns `None` if it cannot be fetched, which is reported on the first call.
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub fn registry_index() -> Option<&'static Index> {
+    static INDEX: Lazy<Result<Index, String>> = Lazy::new(|| {
+        let index = fetch();
+        if let Err(err) = &index {
+            error!("Network": "{err}");
+        }
+        index
+    });
+    INDEX.as_ref().ok()
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+fn fetch() -> Result<Index, String> {
+    let index = registry::registry().fetch_index(
+        registry::INDEX_URL,
+        registry::cache_dir().as_deref(),
+        Index::parse,
+    )?;
+    for malformed in &index.malformed {
+        warn!("Registry": "{malformed}");
+    }
+    Ok(index)
+}
+
+/// An entry of the package index, describing one version of a package
+///
+/// Only the name, version and publication time are required to be valid. Invalid metadata
+/// is left empty, as skipping the entry would make its version look yanked.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub version: PackageVersion,
+    /// When the version was published, in seconds since the Unix epoch
+    #[serde(rename = "updatedAt", default, deserialize_with = "lenient")]
+    pub updated_at: Option<u64>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub description: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub authors: Vec<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub license: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub keywords: Vec<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub categories: Vec<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub repository: Option<String>,
+    /// The minimum compiler version
+    #[serde(default, deserialize_with = "lenient")]
+    pub compiler: Option<VersionBound>,
+}
+
+/// Deserialize a value, falling back to its default if it is invalid
+fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(T::deserialize(value).unwrap_or_default())
+}
+
+/// The package index, with the versions of each package
+#[derive(Debug, Default)]
+pub struct Index {
+    pub packages: HashMap<String, Vec<Entry>>,
+    /// Why entries were skipped, one line each
+    pub malformed: Vec<String>,
+}
+
+impl Index {
+    /// Parse the index, skipping malformed entries instead of failing on them
+    ///
+    /// Only an index that is not a JSON array of objects is rejected as a whole.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let entries = serde_json::from_str::<Vec<Value>>(text)
+            .map_err(|err| format!("Invalid package index: {err}"))?;
+
+        let mut index = Self::default();
+        for (position, value) in entries.into_iter().enumerate() {
+            let name = match value.get("name").and_then(Value::as_str) {
+                Some(name) => format!("package `{name}`"),
+                None => format!("entry #{position}"),
+            };
+            match Entry::deserialize(value) {
+                Ok(entry) => index
+                    .packages
+                    .entry(entry.name.clone())
+                    .or_default()
+                    .push(entry),
+                Err(err) => index
+                    .malformed
+                    .push(format!("Skipped {name} in the package index: {err}")),
+            }
+        }
+        Ok(index)
+    }
+
+    /// The versions of package `name` with their publication times
+    pub fn releases(
+        &self,
+        name: &str,
+    ) -> Option<impl Iterator<Item = (PackageVersion, Option<u64>)> + use<'_>> {
+        self.packages.get(name).map(|entries| {
+            entries
+                .iter()
+                .map(|entry| (entry.version, entry.updated_at))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use typst_syntax::package::PackageVersion;
+
+    use super::Index;
+
+    #[test]
+    fn parse() {
+        let index = Index::parse(
+            r#"[
+                {"name": "cetz", "version": "0.2.1", "description": "Drawing"},
+                {"name": "cetz", "version": "0.3.1", "updatedAt": 1727362412},
+                {"name": "broken", "version": "1.x"},
+                {"name": "partial"},
+                {"version": "1.0.0"},
+                "cetz"
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            index.releases("cetz").unwrap().collect::<Vec<_>>(),
+            vec![
+                (
+                    PackageVersion {
+                        major: 0,
+                        minor: 2,
+                        patch: 1
+                    },
+                    None
+                ),
+                (
+                    PackageVersion {
+                        major: 0,
+                        minor: 3,
+                        patch: 1
+                    },
+                    Some(1727362412)
+                ),
+            ]
+        );
+        assert!(index.releases("broken").is_none());
+        assert_eq!(index.malformed.len(), 4);
+        assert!(
+            index.malformed[0].starts_with("Skipped package `broken` in the package index: "),
+            "{}",
+            index.malformed[0]
+        );
+        assert_eq!(
+            index.malformed[1],
+            "Skipped package `partial` in the package index: missing field `version`"
+        );
+        assert_eq!(
+            index.malformed[2],
+            "Skipped entry #4 in the package index: missing field `name`"
+        );
+    }
+
+    #[test]
+    fn invalid_metadata() {
+        let index = Index::parse(
+            r#"[
+                {"name": "cetz", "version": "0.3.1", "authors": null, "compiler": "x", "keywords": "draw"},
+                {"name": "cetz", "version": "0.3.2", "description": 1, "license": "MIT", "updatedAt": "2024-09-26"}
+            ]"#,
+        )
+        .unwrap();
+        assert!(index.malformed.is_empty());
+        let entries = &index.packages["cetz"];
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].authors.is_empty());
+        assert!(entries[0].compiler.is_none());
+        assert!(entries[0].keywords.is_empty());
+        assert!(entries[1].description.is_none());
+        assert_eq!(entries[1].license.as_deref(), Some("MIT"));
+        assert!(entries[1].updated_at.is_none());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(
+            Index::parse(r#"{"name": "cetz"}"#)
+                .unwrap_err()
+                .starts_with("Invalid package index: ")
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    collections::BTreeSet, fmt::Write as _, io::Write as _, path::PathBuf, process::ExitCode,
+    str::FromStr,
+};
+
+use typst_syntax::{
+    SyntaxNode,
+    ast::{Expr, ModuleImport},
+    package::{PackageSpec, PackageVersion},
+};
+
+use crate::{
+    file,
+    index::{Entry, registry_index},
+};
+
+/// Print the metadata of `package` from the registry, marking the versions `files` import
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub fn run(package: &str, files: &[PathBuf]) -> ExitCode {
+    let name = match package_name(package) {
+        Ok(name) => name,
+        Err(err) => {
+            error!("Info": "{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let Some(index) = registry_index() else {
+        return ExitCode::FAILURE;
+    };
+    let Some(entries) = index.packages.get(name) else {
+        error!("Info": "Package @preview/{name} is not in the registry");
+        return ExitCode::FAILURE;
+    };
+
+    let mut used = BTreeSet::new();
+    for file in files {
+        match file::read_source(file) {
+            Ok(source) => used.extend(imported_versions(&typst_syntax::parse(&source.text), name)),
+            Err(err) => {
+                warn!("Skipped": "{}: {err}", file.display());
+            }
+        }
+    }
+
+    crate::term::stdout()
+        .write_all(describe(name, entries, &used).as_bytes())
+        .expect("Cannot write to stdout");
+    ExitCode::SUCCESS
+}
+
+/// The name of a package given as `@preview/name` or `name`
+fn package_name(package: &str) -> Result<&str, String> {
+    let name = package.strip_prefix("@preview/").unwrap_or(package);
+    if name.is_empty() || name.contains(['@', '/', ':']) {
+        return Err(format!(
+            "Expected a package of the preview namespace like `@preview/cetz`, found `{package}`"
+        ));
+    }
+    Ok(name)
+}
+
+/// Versions of the preview package `name` imported anywhere in `node`
+fn imported_versions(node: &SyntaxNode, name: &str) -> Vec<PackageVersion> {
+    let mut versions = Vec::new();
+    if let Some(import) = node.cast::<ModuleImport>()
+        && let Expr::Str(source) = import.source()
+        && let Ok(package) = PackageSpec::from_str(&source.get())
+        && package.namespace == "preview"
+        && package.name == name
+    {
+        versions.push(package.version);
+    }
+    for child in node.children() {
+        versions.extend(imported_versions(child, name));
+    }
+    versions
+}
+
+/// The metadata of the latest version of package `name`, followed by a table of all its
+/// `entries`, with the `used` versions marked
+fn describe(name: &str, entries: &[Entry], used: &BTreeSet<PackageVersion>) -> String {
+    let mut entries = entries.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.version));
+
+    let mut text = format!("@preview/{name}\n");
+    if let Some(latest) = entries.first() {
+        let fields = [
+            ("Description", latest.description.clone()),
+            ("Authors", Some(latest.authors.join(", "))),
+            ("License", latest.license.clone()),
+            ("Categories", Some(latest.categories.join(", "))),
+            ("Repository", latest.repository.clone()),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value.filter(|value| !value.is_empty()) {
+                let _ = writeln!(text, "{key:<12} {value}");
+            }
+        }
+    }
+
+    let mut rows = entries
+        .iter()
+        .map(|entry| {
+            [
+                entry.version.to_string(),
+                entry
+                    .compiler
+                    .as_ref()
+                    .map_or("-".into(), |compiler| format!(">={compiler}")),
+                entry.updated_at.map_or("-".into(), date),
+                if used.contains(&entry.version) {
+                    "in use".into()
+                } else {
+                    String::new()
+                },
+            ]
+        })
+        .collect::<Vec<_>>();
+    for version in used.iter().rev() {
+        if entries.iter().all(|entry| entry.version != *version) {
+            rows.push([
+                version.to_string(),
+                "-".into(),
+                "-".into(),
+                "in use, yanked".into(),
+            ]);
+        }
+    }
+
+    let header = ["Version", "Compiler", "Released", ""].map(str::to_string);
+    let mut widths = header.clone().map(|cell| cell.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    text.push('\n');
+    for row in std::iter::once(header).chain(rows) {
+        let mut line = String::new();
+        for (cell, width) in row.iter().zip(widths) {
+            let _ = write!(line, "{cell:<width$}  ");
+        }
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+/// The UTC date of `timestamp`, in seconds since the Unix epoch, as `YYYY-MM-DD`
+fn date(timestamp: u64) -> String {
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (timestamp / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeSet, str::FromStr};
+
+    use typst_syntax::package::PackageVersion;
+
+    use crate::index::Index;
+
+    #[test]
+    fn package_name() {
+        assert_eq!(super::package_name("@preview/cetz"), Ok("cetz"));
+        assert_eq!(super::package_name("cetz"), Ok("cetz"));
+        assert!(super::package_name("@local/cetz").is_err());
+        assert!(super::package_name("@preview/cetz:0.2.1").is_err());
+    }
+
+    #[test]
+    fn imported_versions() {
+        let tree = typst_syntax::parse(
+            "#import \"@preview/cetz:0.2.1\"\n#{ import \"@preview/cetz:0.3.1\": canvas }\n#import \"@preview/other:1.0.0\"\n#import \"@local/cetz:9.0.0\"\n",
+        );
+        assert_eq!(
+            super::imported_versions(&tree, "cetz"),
+            [
+                PackageVersion::from_str("0.2.1").unwrap(),
+                PackageVersion::from_str("0.3.1").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn date() {
+        assert_eq!(super::date(0), "1970-01-01");
+        assert_eq!(super::date(951_782_400), "2000-02-29");
+        assert_eq!(super::date(1_727_362_412), "2024-09-26");
+    }
+
+    #[test]
+    fn describe() {
+        let index = Index::parse(
+            r#"[
+                {"name": "cetz", "version": "0.2.1", "updatedAt": 1709251200},
+                {
+                    "name": "cetz",
+                    "version": "0.3.1",
+                    "updatedAt": 1727362412,
+                    "compiler": "0.12.0",
+                    "description": "Drawing with Typst",
+                    "authors": ["Johannes Wolf", "fenjalien"],
+                    "license": "LGPL-3.0-or-later",
+                    "categories": ["visualization"],
+                    "repository": "https://github.com/cetz-package/cetz"
+                }
+            ]"#,
+        )
+        .unwrap();
+        let used = BTreeSet::from([
+            PackageVersion::from_str("0.2.1").unwrap(),
+            PackageVersion::from_str("0.2.0").unwrap(),
+        ]);
+        assert_eq!(
+            super::describe("cetz", &index.packages["cetz"], &used),
+            "@preview/cetz
+Description  Drawing with Typst
+Authors      Johannes Wolf, fenjalien
+License      LGPL-3.0-or-later
+Categories   visualization
+Repository   https://github.com/cetz-package/cetz
+
+Version  Compiler  Released
+0.3.1    >=0.12.0  2024-09-26
+0.2.1    -         2024-03-01  in use
+0.2.0    -         -           in use, yanked
+"
+        );
+    }
+}
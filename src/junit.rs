@@ -0,0 +1,212 @@
+use std::{fmt::Write, path::Path};
+
+use crate::{format::line_col, summary::Summary, upgrade::Report};
+
+/// A JUnit XML report with one test case per checked file
+#[derive(Default)]
+pub struct JunitReport {
+    cases: Vec<TestCase>,
+}
+
+struct TestCase {
+    name: String,
+    outcome: Outcome,
+}
+
+enum Outcome {
+    Passed,
+    /// Outdated imports, one line each
+    Failed(Vec<String>),
+    /// Problems preventing the check, one line each
+    Errored(Vec<String>),
+}
+
+impl JunitReport {
+    /// Record the check of `file`, whose content is `text`, failing if it has outdated imports
+    pub fn add(&mut self, file: &Path, text: &str, report: &Report) {
+        let name = file.display().to_string();
+        let location = |start| {
+            let (line, col) = line_col(text, start);
+            format!("{name}:{line}:{col}")
+        };
+
+        let errors = report
+            .issues
+            .iter()
+            .map(|issue| format!("{}: {}", location(issue.range.start), issue.describe()))
+            .collect::<Vec<_>>();
+        let mut outdated = report
+            .upgrades
+            .iter()
+            .map(|upgrade| (upgrade.range.start, upgrade.describe()))
+            .chain(report.held_back.iter().map(|upgrade| {
+                let message = format!("{} (incompatible)", upgrade.describe());
+                (upgrade.range.start, message)
+            }))
+            .chain(report.refused.iter().map(|upgrade| {
+                let message = format!("{} (would break imported items)", upgrade.describe());
+                (upgrade.range.start, message)
+            }))
+            .collect::<Vec<_>>();
+        outdated.sort_by_key(|(start, _)| *start);
+        let failures = outdated
+            .into_iter()
+            .map(|(start, message)| format!("{}: {message}", location(start)))
+            .collect::<Vec<_>>();
+
+        let outcome = if !errors.is_empty() {
+            Outcome::Errored(errors.into_iter().chain(failures).collect())
+        } else if !failures.is_empty() {
+            Outcome::Failed(failures)
+        } else {
+            Outcome::Passed
+        };
+        self.cases.push(TestCase { name, outcome });
+    }
+
+    /// Record that `file` could not be checked at all
+    pub fn add_error(&mut self, file: &Path, message: &str) {
+        self.cases.push(TestCase {
+            name: file.display().to_string(),
+            outcome: Outcome::Errored(vec![message.to_string()]),
+        });
+    }
+
+    /// The report, with `summary` in the properties of the test suite
+    pub fn to_xml(&self, summary: &Summary) -> String {
+        let count = |f: fn(&Outcome) -> bool| self.cases.iter().filter(|c| f(&c.outcome)).count();
+        let tests = self.cases.len();
+        let failures = count(|outcome| matches!(outcome, Outcome::Failed(_)));
+        let errors = count(|outcome| matches!(outcome, Outcome::Errored(_)));
+        let counts = format!(r#"tests="{tests}" failures="{failures}" errors="{errors}""#);
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let name = env!("CARGO_PKG_NAME");
+        // Writing to a `String` cannot fail
+        let _ = writeln!(xml, r#"<testsuites name="{name}" {counts}>"#);
+        let _ = writeln!(xml, r#"  <testsuite name="{name}" {counts}>"#);
+        xml.push_str("    <properties>\n");
+        for (key, value) in summary.properties() {
+            let _ = writeln!(
+                xml,
+                r#"      <property name="{}" value="{}"/>"#,
+                escape(&key),
+                escape(&value)
+            );
+        }
+        xml.push_str("    </properties>\n");
+        for case in &self.cases {
+            let _ = write!(
+                xml,
+                r#"    <testcase classname="{name}" name="{}""#,
+                escape(&case.name)
+            );
+            let (tag, kind, summary, lines) = match &case.outcome {
+                Outcome::Passed => {
+                    xml.push_str("/>\n");
+                    continue;
+                }
+                Outcome::Failed(lines) => (
+                    "failure",
+                    "outdated",
+                    format!("{} outdated import(s)", lines.len()),
+                    lines,
+                ),
+                Outcome::Errored(lines) => ("error", "unchecked", lines[0].clone(), lines),
+            };
+            let _ = writeln!(
+                xml,
+                r#">
+      <{tag} type="{kind}" message="{}">{}</{tag}>
+    </testcase>"#,
+                escape(&summary),
+                escape(&lines.join("\n")),
+            );
+        }
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::{
+        summary::Summary,
+        upgrade::{Report, Upgrade},
+    };
+
+    use super::JunitReport;
+
+    #[test]
+    fn escape() {
+        assert_eq!(
+            super::escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn to_xml() {
+        let text = "#import \"@preview/pack1:1.0.0\"\n#import \"@preview/pack2:0.1.0\"";
+        let report = Report {
+            has_incompat_versions: true,
+            upgrades: vec![Upgrade::new(
+                text,
+                "@preview/pack1:1.0.0",
+                "@preview/pack1:1.1.0",
+            )],
+            held_back: vec![Upgrade::new(
+                text,
+                "@preview/pack2:0.1.0",
+                "@preview/pack2:0.2.0",
+            )],
+            refused: Vec::new(),
+            issues: Vec::new(),
+        };
+
+        let mut junit = JunitReport::default();
+        junit.add(Path::new("main.typ"), text, &report);
+        junit.add(Path::new("lib.typ"), "", &Report::default());
+        junit.add_error(Path::new("<bad>.typ"), "Invalid UTF-8 at byte 2");
+        assert_eq!(
+            junit.to_xml(&Summary::default()),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="typst-upgrade" tests="3" failures="1" errors="1">
+  <testsuite name="typst-upgrade" tests="3" failures="1" errors="1">
+    <properties>
+      <property name="files-scanned" value="0"/>
+      <property name="files-outdated" value="0"/>
+      <property name="files-changed" value="0"/>
+      <property name="errors" value="0"/>
+    </properties>
+    <testcase classname="typst-upgrade" name="main.typ">
+      <failure type="outdated" message="2 outdated import(s)">main.typ:1:9: @preview/pack1 1.0.0 can be upgraded to 1.1.0
+main.typ:2:9: @preview/pack2 0.1.0 can be upgraded to 0.2.0 (incompatible)</failure>
+    </testcase>
+    <testcase classname="typst-upgrade" name="lib.typ"/>
+    <testcase classname="typst-upgrade" name="&lt;bad&gt;.typ">
+      <error type="unchecked" message="Invalid UTF-8 at byte 2">Invalid UTF-8 at byte 2</error>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
@@ -1,53 +1,255 @@
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
 
 use std::{
+    collections::HashSet,
     fs,
+    io::Write,
     path::{Path, PathBuf},
     process::ExitCode,
+    time::{Duration, SystemTime},
 };
 
-use clap::{ColorChoice, Parser};
+use changelog::ChangelogSource;
+use clap::{ArgAction, ColorChoice, Parser, Subcommand};
+use constraint::{Age, Constraint, Constraints};
 use diffline::DiffChoice;
+use format::OutputFormat;
+use outcome::{FailOn, Outcome};
+use regex::Regex;
+use typst_syntax::package::PackageVersion;
 
-use crate::upgrade::TypstNodeUpgrader;
+use crate::{
+    git::PendingFile,
+    junit::JunitReport,
+    migrate::Migrations,
+    rename::Renames,
+    sarif::SarifLog,
+    summary::Summary,
+    upgrade::{Resolver, TypstNodeUpgrader},
+};
 
 #[macro_use]
 mod term;
+mod changelog;
+mod constraint;
 mod diffline;
+mod exports;
+mod file;
+mod format;
+mod git;
+mod index;
+mod info;
+mod junit;
+mod migrate;
+mod outcome;
+mod package;
+mod registry;
+mod rename;
+mod sarif;
+mod search;
+mod summary;
 mod upgrade;
+mod watch;
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
-    /// Dry run without editing files, exit with `73` if there are changes
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Dry run without editing files, exit with a non-zero code if there are changes (see "Exit Codes" in the README)
     #[arg(short, long)]
     dry_run: bool,
 
+    /// Exit with a non-zero code when upgrades of this kind are available
+    #[arg(long, value_name = "UPGRADES")]
+    fail_on: Option<FailOn>,
+
     /// Allow incompatible upgrades
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     incompatible: bool,
 
     /// Colorize output
-    #[arg(long, default_value_t = ColorChoice::Auto)]
+    #[arg(long, default_value_t = ColorChoice::Auto, global = true)]
     color: ColorChoice,
 
     /// Diff style
     #[arg(long, default_value_t = DiffChoice::Short)]
     diff: DiffChoice,
 
-    /// Print more information
-    #[arg(short, long)]
-    verbose: bool,
+    /// Output format of the findings, in addition to or instead of the text output
+    #[arg(long, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Load extra package renames and deprecations from a file
+    #[arg(long, value_name = "FILE", global = true)]
+    renames: Vec<PathBuf>,
+
+    /// Apply API migration rules from a file to the files importing upgraded packages
+    #[arg(long, value_name = "FILE")]
+    migrate: Vec<PathBuf>,
+
+    /// Keep a copy of each updated file with the given suffix
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "~"
+    )]
+    backup: Option<String>,
+
+    /// Commit the upgrades of each package separately in the git repository
+    #[arg(long, conflicts_with_all = ["dry_run", "backup"])]
+    git_commit: bool,
+
+    /// Allow editing files with uncommitted changes or untracked by git
+    #[arg(long)]
+    allow_dirty: bool,
+
+    /// Refuse upgrades that would break imported items, if the new version is cached locally
+    #[arg(long)]
+    strict_imports: bool,
+
+    /// Never upgrade to versions matching this regular expression, e.g. `^0\.1\.`
+    #[arg(long, value_name = "REGEX")]
+    exclude_version: Vec<Regex>,
+
+    /// Bound the versions a package may be upgraded to, e.g. `cetz=>=0.2,<0.4`
+    #[arg(long, value_name = "PACKAGE=BOUNDS")]
+    constraint: Vec<Constraint>,
+
+    /// Ignore versions published more recently than this, e.g. `7d`, `12h` or `2w`
+    #[arg(long, value_name = "AGE")]
+    min_age: Option<Age>,
+
+    /// Move imports of yanked versions without an upgrade to the nearest compatible version
+    #[arg(long)]
+    fix_yanked: bool,
+
+    /// Print the changelog entries between the old and new version of each upgrade
+    #[arg(long, value_name = "SOURCE", num_args = 0..=1, default_missing_value = "cache")]
+    changelog: Option<ChangelogSource>,
+
+    /// Print more information, twice for debugging details
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Print only errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Proxy for registry requests
+    #[arg(
+        long,
+        value_name = "URL",
+        env = "TYPST_UPGRADE_PROXY",
+        global = true,
+        help_heading = "Network"
+    )]
+    proxy: Option<String>,
+
+    /// PEM bundle of extra CA certificates to trust
+    #[arg(
+        long,
+        value_name = "FILE",
+        env = "TYPST_UPGRADE_CA_CERT",
+        global = true,
+        help_heading = "Network"
+    )]
+    ca_cert: Option<PathBuf>,
+
+    /// Seconds to wait for a connection to the registry
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 10,
+        env = "TYPST_UPGRADE_CONNECT_TIMEOUT",
+        global = true,
+        help_heading = "Network"
+    )]
+    connect_timeout: u64,
+
+    /// Seconds to wait for a whole registry request
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 60,
+        env = "TYPST_UPGRADE_TIMEOUT",
+        global = true,
+        help_heading = "Network"
+    )]
+    timeout: u64,
+
+    /// How often to retry failed registry requests
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 5,
+        env = "TYPST_UPGRADE_RETRIES",
+        global = true,
+        help_heading = "Network"
+    )]
+    retries: u32,
+
+    /// Milliseconds to wait before the first retry, doubled for each further one
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 500,
+        env = "TYPST_UPGRADE_RETRY_BACKOFF",
+        global = true,
+        help_heading = "Network"
+    )]
+    retry_backoff: u64,
 
     /// Typst entry paths
     #[arg(value_name = "TYPST_ENTRY_PATHS", required = true)]
     entries: Vec<PathBuf>,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Watch Typst files and report outdated imports as they are introduced
+    Watch {
+        /// Typst entry paths
+        #[arg(value_name = "TYPST_ENTRY_PATHS", required = true)]
+        entries: Vec<PathBuf>,
+    },
+    /// Show the metadata and versions of a package, marking those the project imports
+    Info {
+        /// Package name, e.g. `@preview/cetz`
+        package: String,
+        /// Typst entry paths of the project
+        #[arg(value_name = "TYPST_ENTRY_PATHS", default_value = ".")]
+        entries: Vec<PathBuf>,
+    },
+    /// Search the registry for packages by name, description, keywords and categories
+    Search {
+        /// Text to search for, case-insensitively
+        query: String,
+        /// Only show packages in this category, e.g. `visualization`
+        #[arg(long)]
+        category: Option<String>,
+        /// Only show versions supporting this compiler version, e.g. `0.12`
+        #[arg(long, value_name = "VERSION", value_parser = search::parse_compiler)]
+        compiler: Option<PackageVersion>,
+    },
+}
+
 #[cfg_attr(coverage_nightly, coverage(off))]
 fn main() -> ExitCode {
     std::panic::set_hook(Box::new(|info| {
-        if let Some(info) = info.payload().downcast_ref::<&str>() {
+        let payload = info.payload();
+        if let Some(info) = payload.downcast_ref::<&str>() {
+            error!("Fatal": "{}", info);
+        } else if let Some(info) = payload.downcast_ref::<String>() {
             error!("Fatal": "{}", info);
         }
     }));
@@ -55,45 +257,252 @@ fn main() -> ExitCode {
     let args = Cli::parse();
 
     term::init(args.color);
+    term::init_level(match (args.quiet, args.verbose) {
+        (true, _) => term::Level::Quiet,
+        (false, 0) => term::Level::Normal,
+        (false, 1) => term::Level::Verbose,
+        (false, _) => term::Level::Debug,
+    });
     diffline::init(args.diff);
+    if args.format.is_report() {
+        term::redirect_stdout();
+    }
+    if let Err(err) = registry::init(registry::Config {
+        proxy: args.proxy.clone(),
+        ca_cert: args.ca_cert.clone(),
+        connect_timeout: Duration::from_secs(args.connect_timeout),
+        timeout: Duration::from_secs(args.timeout),
+        retries: args.retries,
+        backoff: Duration::from_millis(args.retry_backoff),
+    }) {
+        error!("Network": "{err}");
+        return ExitCode::FAILURE;
+    }
 
-    let mut typst_files = args
-        .entries
-        .iter()
-        .flat_map(find_all_typst_files)
-        .collect::<Vec<_>>();
+    if let Some(Command::Search {
+        query,
+        category,
+        compiler,
+    }) = &args.command
+    {
+        return search::run(query, category.as_deref(), *compiler);
+    }
+
+    let entries = match &args.command {
+        Some(Command::Watch { entries } | Command::Info { entries, .. }) => entries,
+        Some(Command::Search { .. }) => unreachable!("Searching needs no files"),
+        None => &args.entries,
+    };
+    let mut typst_files = Vec::new();
+    for entry in entries {
+        match find_all_typst_files(entry) {
+            Ok(files) => typst_files.extend(files),
+            Err(err) => {
+                error!("{err}");
+                return ExitCode::from(outcome::ERRORS);
+            }
+        }
+    }
 
     typst_files.sort_unstable();
     typst_files.dedup();
 
     let typst_files = typst_files;
+    debug!("Found": "{} Typst file(s)", typst_files.len());
+
+    if let Some(Command::Info { package, .. }) = &args.command {
+        return info::run(package, &typst_files);
+    }
+
+    let mut renames = Renames::builtin();
+    for path in &args.renames {
+        let loaded = fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read renames file {}: {err}", path.display()))
+            .and_then(|text| {
+                renames
+                    .extend_from_str(&text)
+                    .map_err(|err| format!("Invalid renames file {}: {err}", path.display()))
+            });
+        if let Err(err) = loaded {
+            error!("{err}");
+            return ExitCode::from(outcome::ERRORS);
+        }
+    }
+
+    let mut constraints = Constraints::new(args.exclude_version.clone(), args.constraint.clone());
+    if let Some(age) = args.min_age {
+        constraints = constraints.min_age(age, SystemTime::now());
+    }
+    let resolver = Resolver::registry().constraints(constraints);
+    let package_roots = package::package_roots();
+
+    if let Some(Command::Watch { entries }) = &args.command {
+        return watch::run(entries, &typst_files, |file| {
+            let source = file::read_source(file)
+                .map_err(|err| {
+                    error!("Skipped": "{}: {err}", file.display());
+                })
+                .ok()?;
+            let tree = typst_syntax::parse(&source.text);
+            let (_, report) = term::silenced(|| {
+                TypstNodeUpgrader::new(&tree, &resolver, !args.incompatible)
+                    .check_imports(&package_roots, args.strict_imports)
+                    .fix_yanked(args.fix_yanked)
+                    .renames(&renames)
+                    .convert()
+            });
+            Some(report)
+        });
+    }
 
-    let mut exit_code = ExitCode::SUCCESS;
+    let mut migrations = Migrations::default();
+    for path in &args.migrate {
+        let loaded = fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read migration file {}: {err}", path.display()))
+            .and_then(|text| {
+                migrations
+                    .extend_from_str(&text)
+                    .map_err(|err| format!("Invalid migration file {}: {err}", path.display()))
+            });
+        if let Err(err) = loaded {
+            error!("{err}");
+            return ExitCode::from(outcome::ERRORS);
+        }
+    }
+
+    if args.git_commit {
+        let mut repos = HashSet::new();
+        for file in &typst_files {
+            let Some(repo) = git::toplevel(file) else {
+                error!("{} is not in a git repository", file.display());
+                return ExitCode::FAILURE;
+            };
+            repos.insert(repo);
+        }
+        for repo in repos {
+            if !args.allow_dirty && !git::is_clean(&repo).unwrap_or(false) {
+                error!(
+                    "Working tree {} is dirty, commit or stash the changes first, or pass `--allow-dirty`",
+                    repo.display(),
+                );
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let mut outcome = Outcome::default();
+    let mut pending = Vec::new();
+    let mut pending_reports = Vec::new();
     let mut incompat_versions_available = false;
+    let mut changelogs_shown = HashSet::new();
+    let mut sarif_log = SarifLog::default();
+    let mut junit_report = JunitReport::default();
+    let mut summary = Summary::default();
 
     for file in &typst_files {
-        let ext = file.extension().unwrap();
-        let content = fs::read_to_string(file).expect("Cannot read file");
-        let tree = if ext == "typ" || ext == "typst" {
-            typst_syntax::parse(&content)
-        } else {
-            panic!("Unknown file extension of: {}", file.display());
+        let source = match file::read_source(file) {
+            Ok(source) => source,
+            Err(err) => {
+                error!("Skipped": "{}: {err}", file.display());
+                junit_report.add_error(file, &err);
+                summary.skipped();
+                outcome.error();
+                continue;
+            }
         };
+        let tree = typst_syntax::parse(&source.text);
         info!("Checking": "{}", file.display());
-        let (result, has_incompat_versions) =
-            TypstNodeUpgrader::new(&tree, args.verbose, !args.incompatible).convert();
-        incompat_versions_available |= has_incompat_versions;
+        let (result, report) = TypstNodeUpgrader::new(&tree, &resolver, !args.incompatible)
+            .check_imports(&package_roots, args.strict_imports)
+            .fix_yanked(args.fix_yanked)
+            .renames(&renames)
+            .convert();
+        let result = migrations.apply(&result, &report.upgrades);
+        incompat_versions_available |= report.has_incompat_versions;
+        outcome.record(&report);
+        match args.format {
+            OutputFormat::Text => (),
+            OutputFormat::Github => {
+                for command in format::github(file, &source.text, &report) {
+                    println!("{command}");
+                }
+            }
+            OutputFormat::Sarif => sarif_log.add(file, &source.text, &report),
+            OutputFormat::Junit => junit_report.add(file, &source.text, &report),
+        }
+        let mut written = false;
         if tree != result {
             let old = tree.into_text();
             let new = result.into_text();
             diffline::show(&old, &new);
+            if let Some(source) = args.changelog {
+                for upgrade in &report.upgrades {
+                    // Changelogs of replaced packages do not cover the old package
+                    if upgrade.from.name == upgrade.to.name
+                        && changelogs_shown.insert((upgrade.from.clone(), upgrade.to.clone()))
+                    {
+                        changelog::show(&upgrade.from, &upgrade.to, source);
+                    }
+                }
+            }
             if args.dry_run {
-                exit_code = ExitCode::from(73);
+                // Only reported, and through the exit code
+            } else if args.git_commit {
+                pending.push(PendingFile {
+                    path: file.clone(),
+                    source,
+                    upgrades: report.upgrades.clone(),
+                });
+                // Recorded once the commits are made
+                pending_reports.push(report);
+                continue;
+            } else if !args.allow_dirty && !git::is_file_clean(file) {
+                warn!(
+                    "Skipped": "{} has uncommitted changes, pass `--allow-dirty` to update it anyway",
+                    file.display(),
+                );
             } else {
                 info!("Updating": "{}", file.display());
-                fs::write(file, new.to_string()).expect("Cannot write file");
+                let content = source.encode(&new);
+                match file::write_atomic(file, content.as_bytes(), args.backup.as_deref()) {
+                    Ok(()) => written = true,
+                    Err(err) => {
+                        error!("Failed": "Cannot write {}: {err}", file.display());
+                        summary.error();
+                        outcome.error();
+                    }
+                }
+            }
+        }
+        summary.record(&report, written);
+    }
+
+    let committed = git::commit_by_package(&pending, &migrations);
+    if let Err(err) = &committed {
+        error!("Git": "{err}");
+        summary.error();
+        outcome.error();
+    }
+    for report in &pending_reports {
+        summary.record(report, committed.is_ok());
+    }
+
+    match args.format {
+        OutputFormat::Text => (),
+        OutputFormat::Github => {
+            if let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY") {
+                let written = fs::OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(path)
+                    .and_then(|mut file| file.write_all(summary.markdown().as_bytes()));
+                if let Err(err) = written {
+                    warn!("Summary": "Cannot write the job summary: {err}");
+                }
             }
         }
+        OutputFormat::Sarif => println!("{:#}", sarif_log.to_json(&summary)),
+        OutputFormat::Junit => print!("{}", junit_report.to_xml(&summary)),
     }
 
     if incompat_versions_available {
@@ -102,10 +511,13 @@ fn main() -> ExitCode {
         );
     }
 
-    exit_code
+    summary.show();
+
+    ExitCode::from(outcome.exit_code(args.fail_on, args.dry_run))
 }
 
-fn find_all_typst_files(path: impl AsRef<Path>) -> Vec<PathBuf> {
+/// The Typst files at `path`, which may be a Typst file or a directory searched recursively
+fn find_all_typst_files(path: impl AsRef<Path>) -> Result<Vec<PathBuf>, String> {
     fn find_all_typst_files_inner(path: impl AsRef<Path>) -> Option<Vec<PathBuf>> {
         let mut result = Vec::new();
         let path = path.as_ref();
@@ -136,11 +548,22 @@ fn find_all_typst_files(path: impl AsRef<Path>) -> Vec<PathBuf> {
         Some(result)
     }
 
-    if !path.as_ref().exists() {
-        panic!("Path does not exist: {}", path.as_ref().display());
+    let path = path.as_ref();
+    if !path.exists() {
+        return Err(format!("Path does not exist: {}", path.display()));
+    }
+    if path.is_file()
+        && !path
+            .extension()
+            .is_some_and(|ext| ext == "typ" || ext == "typst")
+    {
+        return Err(format!(
+            "Unknown file extension, expected .typ or .typst: {}",
+            path.display()
+        ));
     }
 
-    find_all_typst_files_inner(path).unwrap_or_default()
+    Ok(find_all_typst_files_inner(path).unwrap_or_default())
 }
 
 #[cfg(test)]
@@ -150,9 +573,21 @@ mod test {
     use crate::find_all_typst_files;
 
     #[test]
-    #[should_panic]
     fn should_not_find_typst_files_in_non_existent_dir() {
-        find_all_typst_files("non-existent-dir");
+        assert_eq!(
+            find_all_typst_files("non-existent-dir"),
+            Err("Path does not exist: non-existent-dir".into())
+        );
+    }
+
+    #[test]
+    fn should_not_accept_other_files() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        assert!(
+            find_all_typst_files(path)
+                .unwrap_err()
+                .starts_with("Unknown file extension, expected .typ or .typst: ")
+        );
     }
 
     #[test]
@@ -160,7 +595,7 @@ mod test {
         let path = Path::new(&env!("CARGO_MANIFEST_DIR").to_string())
             .join("tests")
             .join("proj");
-        let files = find_all_typst_files(path);
+        let files = find_all_typst_files(path).unwrap();
         assert!(files.iter().all(|f| f.exists() && f.is_file()));
         assert!(files.iter().all(|f| {
             f.extension()
@@ -0,0 +1,556 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
+
+use serde::Deserialize;
+use typst_syntax::{
+    SyntaxKind, SyntaxNode,
+    ast::{Expr, ImportItem, Imports, LetBinding, ModuleImport},
+    package::{PackageSpec, PackageVersion},
+};
+
+use crate::upgrade::Upgrade;
+
+/// API migration rules for one package, applied when upgrading across `since`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Migration {
+    /// Name of the package the rules belong to
+    pub package: String,
+    /// The version introducing the API changes
+    pub since: PackageVersion,
+    /// Identifiers or dotted paths to rename, e.g. `"draw.content" = "draw.text"`
+    #[serde(default)]
+    pub rename: BTreeMap<String, String>,
+    /// Named arguments to rename, keyed by the (old) function path
+    #[serde(default)]
+    pub arguments: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MigrationFile {
+    #[serde(default)]
+    migration: Vec<Migration>,
+}
+
+#[derive(Debug, Default)]
+pub struct Migrations(Vec<Migration>);
+
+impl Migrations {
+    /// Add the rules of a TOML migration file
+    ///
+    /// ```toml
+    /// [[migration]]
+    /// package = "cetz"
+    /// since = "0.3.0"
+    /// rename = { "draw.content" = "draw.text" }
+    /// arguments."draw.content" = { anchor = "align" }
+    /// ```
+    pub fn extend_from_str(&mut self, text: &str) -> Result<(), String> {
+        let file: MigrationFile = toml::from_str(text).map_err(|err| err.message().to_string())?;
+        self.0.extend(file.migration);
+        Ok(())
+    }
+
+    /// Apply the rules matching `upgrades` to the syntax tree
+    ///
+    /// A rule matches an upgrade of its package from a version below `since` to a version
+    /// at or above it. Only names bound by an import of the package are rewritten: its
+    /// imported items, fields of its module, and with a wildcard import any name the file
+    /// does not bind itself with `let`.
+    pub fn apply(&self, root: &SyntaxNode, upgrades: &[Upgrade]) -> SyntaxNode {
+        let mut matching = self
+            .0
+            .iter()
+            .filter(|migration| {
+                upgrades.iter().any(|upgrade| {
+                    upgrade.from.name == migration.package
+                        && upgrade.to.name == migration.package
+                        && upgrade.from.version < migration.since
+                        && migration.since <= upgrade.to.version
+                })
+            })
+            .collect::<Vec<_>>();
+        // Chained renames only work oldest first, wherever the rules were loaded from
+        matching.sort_by_key(|migration| migration.since);
+
+        let mut root = root.clone();
+        for migration in matching {
+            let mut bindings = Bindings::default();
+            bindings.collect(&root, &migration.package);
+            let mut count = 0;
+            root = migrate(&root, migration, &bindings, &mut count);
+            if count > 0 {
+                info!(
+                    "Migrated": "{count} usage(s) for {} {}",
+                    migration.package,
+                    migration.since,
+                );
+            }
+        }
+        root
+    }
+}
+
+/// The names under which a file refers to the items of a package
+#[derive(Debug, Default)]
+struct Bindings {
+    /// Items imported by name, e.g. `draw` in `#import "@preview/cetz:0.3.0": draw`
+    items: BTreeSet<String>,
+    /// Names of the package module, e.g. `cetz` in `#import "@preview/cetz:0.3.0"`
+    modules: BTreeSet<String>,
+    /// Whether all items are imported with `*`
+    wildcard: bool,
+    /// Names bound with `let`, which shadow a wildcard import
+    local: BTreeSet<String>,
+}
+
+impl Bindings {
+    fn collect(&mut self, node: &SyntaxNode, package: &str) {
+        if let Some(import) = node.cast::<ModuleImport>() {
+            if imports_package(import, package) {
+                if let Some(name) = import.new_name() {
+                    self.modules.insert(name.get().to_string());
+                }
+                match import.imports() {
+                    None if import.new_name().is_none() => {
+                        self.modules
+                            .extend(import.bare_name().map(|name| name.to_string()));
+                    }
+                    None => (),
+                    Some(Imports::Wildcard) => self.wildcard = true,
+                    Some(Imports::Items(items)) => {
+                        for item in items.iter() {
+                            if let ImportItem::Simple(path) = item
+                                && path.iter().count() == 1
+                            {
+                                self.items.insert(path.name().get().to_string());
+                            }
+                        }
+                    }
+                }
+            }
+        } else if let Some(binding) = node.cast::<LetBinding>() {
+            self.local.extend(
+                binding
+                    .kind()
+                    .bindings()
+                    .into_iter()
+                    .map(|ident| ident.get().to_string()),
+            );
+        }
+        for child in node.children() {
+            self.collect(child, package);
+        }
+    }
+
+    /// The path of an item within the package that `path` refers to, if any
+    ///
+    /// E.g. `draw.content` for `cetz.draw.content` or, with `draw` imported, `draw.content`.
+    fn resolve<'p>(&self, path: &'p str) -> Option<&'p str> {
+        let (first, rest) = match path.split_once('.') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (path, None),
+        };
+        if self.items.contains(first) || (self.wildcard && !self.local.contains(first)) {
+            Some(path)
+        } else if self.modules.contains(first) {
+            rest
+        } else {
+            None
+        }
+    }
+
+    /// `path` with the item it refers to renamed by `rename`, keeping a module prefix
+    fn rename(&self, path: &str, rename: &BTreeMap<String, String>) -> Option<String> {
+        let item = self.resolve(path)?;
+        let new = rename.get(item)?;
+        Some(format!("{}{new}", &path[..path.len() - item.len()]))
+    }
+}
+
+/// Whether `import` imports `package`, in any version
+fn imports_package(import: ModuleImport, package: &str) -> bool {
+    matches!(
+        import.source(),
+        Expr::Str(source) if PackageSpec::from_str(&source.get()).is_ok_and(|spec| spec.name == package)
+    )
+}
+
+fn migrate(
+    node: &SyntaxNode,
+    migration: &Migration,
+    bindings: &Bindings,
+    count: &mut usize,
+) -> SyntaxNode {
+    match node.kind() {
+        SyntaxKind::Ident | SyntaxKind::MathIdent => {
+            if let Some(new) = bindings.rename(node.text(), &migration.rename) {
+                return replace_path(node, &new, count);
+            }
+        }
+        SyntaxKind::FieldAccess => {
+            if let Some(new) = bindings.rename(&compact(node), &migration.rename) {
+                return replace_path(node, &new, count);
+            }
+            // Only the target may refer to a renamed item, not the field name
+            return map_children(node, |i, child| match i {
+                0 => migrate(child, migration, bindings, count),
+                _ => child.clone(),
+            });
+        }
+        SyntaxKind::ModuleImport => {
+            let Some(import) = node.cast::<ModuleImport>() else {
+                return node.clone();
+            };
+            if !imports_package(import, &migration.package) {
+                return node.clone();
+            }
+            // The imported paths always refer to the package, whatever they are bound to
+            return map_children(node, |_, child| match child.kind() {
+                SyntaxKind::ImportItems => map_children(child, |_, item| match item.kind() {
+                    SyntaxKind::ImportItemPath => rename_item(item, migration, count),
+                    SyntaxKind::RenamedImportItem => map_children(item, |i, path| match i {
+                        0 => rename_item(path, migration, count),
+                        _ => path.clone(),
+                    }),
+                    _ => item.clone(),
+                }),
+                _ => child.clone(),
+            });
+        }
+        SyntaxKind::Named => {
+            // Argument and dictionary keys are not identifiers in scope
+            return map_children(node, |i, child| match i {
+                0 => child.clone(),
+                _ => migrate(child, migration, bindings, count),
+            });
+        }
+        SyntaxKind::FuncCall | SyntaxKind::SetRule => {
+            let callee = node
+                .children()
+                .find(|child| matches!(child.kind(), SyntaxKind::Ident | SyntaxKind::FieldAccess));
+            if let Some(arguments) = callee.and_then(|callee| {
+                let path = compact(callee);
+                bindings
+                    .resolve(&path)
+                    .and_then(|item| migration.arguments.get(item))
+            }) {
+                return map_children(node, |_, child| match child.kind() {
+                    SyntaxKind::Args => map_children(child, |_, arg| {
+                        let renamed = rename_argument(arg, arguments, count);
+                        migrate(&renamed, migration, bindings, count)
+                    }),
+                    _ => migrate(child, migration, bindings, count),
+                });
+            }
+        }
+        _ => (),
+    }
+
+    if node.children().len() == 0 {
+        node.clone()
+    } else {
+        map_children(node, |_, child| migrate(child, migration, bindings, count))
+    }
+}
+
+/// Rename an imported path like `draw.content`, if the new path has as many segments
+fn rename_item(path: &SyntaxNode, migration: &Migration, count: &mut usize) -> SyntaxNode {
+    let segments = path
+        .children()
+        .filter(|child| child.kind() == SyntaxKind::Ident)
+        .count();
+    let Some(new) = migration
+        .rename
+        .get(&compact(path))
+        .filter(|new| new.split('.').count() == segments)
+    else {
+        return path.clone();
+    };
+    *count += 1;
+    let mut new = new.split('.');
+    map_children(path, |_, child| match child.kind() {
+        SyntaxKind::Ident => SyntaxNode::leaf(SyntaxKind::Ident, new.next().unwrap_or_default()),
+        _ => child.clone(),
+    })
+}
+
+fn rename_argument(
+    arg: &SyntaxNode,
+    arguments: &BTreeMap<String, String>,
+    count: &mut usize,
+) -> SyntaxNode {
+    if arg.kind() != SyntaxKind::Named {
+        return arg.clone();
+    }
+    map_children(arg, |i, child| match arguments.get(child.text().as_str()) {
+        Some(new) if i == 0 && child.kind() == SyntaxKind::Ident => {
+            *count += 1;
+            SyntaxNode::leaf(SyntaxKind::Ident, new.as_str())
+        }
+        _ => child.clone(),
+    })
+}
+
+fn map_children(
+    node: &SyntaxNode,
+    mut f: impl FnMut(usize, &SyntaxNode) -> SyntaxNode,
+) -> SyntaxNode {
+    SyntaxNode::inner(
+        node.kind(),
+        node.children()
+            .enumerate()
+            .map(|(i, child)| f(i, child))
+            .collect(),
+    )
+}
+
+/// The text of `node` without trivia, e.g. `draw.content` for `draw .content`
+fn compact(node: &SyntaxNode) -> String {
+    node.clone()
+        .into_text()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+/// Replace the identifier or field access `node` by the dotted path `new`
+///
+/// In math, where e.g. `pack-v2` would read as `pack - v2`, paths that are not a single
+/// identifier or field access there are left alone with a warning.
+fn replace_path(node: &SyntaxNode, new: &str, count: &mut usize) -> SyntaxNode {
+    let math = in_math(node);
+    match parse_path(new, math) {
+        Some(new) => {
+            *count += 1;
+            new
+        }
+        None if math => {
+            warn!(
+                "Migration": "Cannot rename `{}` to `{new}` in math, use `#{new}` instead",
+                compact(node),
+            );
+            node.clone()
+        }
+        None => node.clone(),
+    }
+}
+
+/// Whether the identifier or field access `node` is in math mode
+fn in_math(node: &SyntaxNode) -> bool {
+    match node.kind() {
+        SyntaxKind::MathIdent => true,
+        SyntaxKind::FieldAccess => node.children().next().is_some_and(in_math),
+        _ => false,
+    }
+}
+
+/// Parse a dotted path like `draw.text` into an identifier or field access node, in code
+/// or math mode, if it parses as a single one
+fn parse_path(path: &str, math: bool) -> Option<SyntaxNode> {
+    let root = if math {
+        typst_syntax::parse_math(path)
+    } else {
+        typst_syntax::parse_code(path)
+    };
+    match root.children().as_slice() {
+        [node]
+            if matches!(
+                node.kind(),
+                SyntaxKind::Ident | SyntaxKind::MathIdent | SyntaxKind::FieldAccess
+            ) && node.clone().into_text() == path =>
+        {
+            Some(node.clone())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::upgrade::Upgrade;
+
+    use super::Migrations;
+
+    const RULES: &str = r#"
+        [[migration]]
+        package = "pack1"
+        since = "2.0.0"
+        rename = { pack = "pack-v2", "draw.content" = "draw.text" }
+        arguments."draw.content" = { anchor = "align" }
+        arguments.pack = { size = "scale" }
+
+        [[migration]]
+        package = "pack1"
+        since = "3.0.0"
+        rename = { pack-v2 = "pack-v3" }
+    "#;
+
+    #[test]
+    fn apply() {
+        let mut migrations = Migrations::default();
+        migrations.extend_from_str(RULES).unwrap();
+
+        let tree = typst_syntax::parse(concat!(
+            "#import \"@preview/pack1:2.0.0\": pack, draw\n",
+            "#pack(size: 2, (size: 1), anchor: 3)\n",
+            "#draw.content(anchor: \"north\")[content]\n",
+            "#set draw.content(anchor: \"south\")\n",
+            "#other.pack $pack(x) + draw.content$\n",
+        ));
+        let expected = concat!(
+            "#import \"@preview/pack1:2.0.0\": pack-v2, draw\n",
+            "#pack-v2(scale: 2, (size: 1), anchor: 3)\n",
+            "#draw.text(align: \"north\")[content]\n",
+            "#set draw.text(align: \"south\")\n",
+            "#other.pack $pack(x) + draw.text$\n",
+        );
+
+        let result = migrations.apply(
+            &tree,
+            &[Upgrade::new(
+                "",
+                "@preview/pack1:1.1.0",
+                "@preview/pack1:2.0.0",
+            )],
+        );
+        assert_eq!(result.into_text(), expected);
+
+        for upgrades in [
+            vec![],
+            vec![Upgrade::new(
+                "",
+                "@preview/pack1:2.0.0",
+                "@preview/pack1:2.1.0",
+            )],
+            vec![Upgrade::new(
+                "",
+                "@preview/pack1:1.0.0",
+                "@preview/pack1:1.1.0",
+            )],
+            vec![Upgrade::new(
+                "",
+                "@preview/pack2:1.0.0",
+                "@preview/pack2:2.0.0",
+            )],
+        ] {
+            assert_eq!(migrations.apply(&tree, &upgrades), tree);
+        }
+
+        let result = migrations.apply(
+            &tree,
+            &[Upgrade::new(
+                "",
+                "@preview/pack1:1.0.0",
+                "@preview/pack1:3.0.0",
+            )],
+        );
+        assert!(
+            result
+                .into_text()
+                .starts_with("#import \"@preview/pack1:2.0.0\": pack-v3, draw\n")
+        );
+    }
+
+    #[test]
+    fn order() {
+        // Chained rules given newest first, as if from separate files
+        let mut migrations = Migrations::default();
+        migrations
+            .extend_from_str(
+                "[[migration]]\npackage = \"pack1\"\nsince = \"3.0.0\"\nrename = { pack-v2 = \"pack-v3\" }",
+            )
+            .unwrap();
+        migrations
+            .extend_from_str(
+                "[[migration]]\npackage = \"pack1\"\nsince = \"2.0.0\"\nrename = { pack = \"pack-v2\" }",
+            )
+            .unwrap();
+
+        let tree = typst_syntax::parse("#import \"@preview/pack1:3.0.0\": pack\n#pack\n");
+        let result = migrations.apply(
+            &tree,
+            &[Upgrade::new(
+                "",
+                "@preview/pack1:1.0.0",
+                "@preview/pack1:3.0.0",
+            )],
+        );
+        assert_eq!(
+            result.into_text(),
+            "#import \"@preview/pack1:3.0.0\": pack-v3\n#pack-v3\n"
+        );
+    }
+
+    #[test]
+    fn bindings() {
+        let mut migrations = Migrations::default();
+        migrations.extend_from_str(RULES).unwrap();
+        let upgrades = [Upgrade::new(
+            "",
+            "@preview/pack1:1.1.0",
+            "@preview/pack1:2.0.0",
+        )];
+        let migrate = |text| {
+            migrations
+                .apply(&typst_syntax::parse(text), &upgrades)
+                .into_text()
+        };
+
+        // Local names that happen to match a rule are left alone
+        assert_eq!(
+            migrate(
+                "#import \"@preview/pack1:2.0.0\": draw\n#let pack = 1\n#pack #draw.content()\n"
+            ),
+            "#import \"@preview/pack1:2.0.0\": draw\n#let pack = 1\n#pack #draw.text()\n"
+        );
+        assert_eq!(
+            migrate(
+                "#import \"@preview/pack1:2.0.0\": *\n#let pack(size: 1) = size\n#pack(size: 2)\n"
+            ),
+            "#import \"@preview/pack1:2.0.0\": *\n#let pack(size: 1) = size\n#pack(size: 2)\n"
+        );
+        assert_eq!(
+            migrate("#import \"utils.typ\": pack\n#import \"@preview/pack1:2.0.0\"\n#pack\n"),
+            "#import \"utils.typ\": pack\n#import \"@preview/pack1:2.0.0\"\n#pack\n"
+        );
+
+        // Fields of the module and renamed items refer to the package
+        assert_eq!(
+            migrate(
+                "#import \"@preview/pack1:2.0.0\" as p: pack as p2\n#p.pack(size: 1) #p.draw.content() #p2\n"
+            ),
+            "#import \"@preview/pack1:2.0.0\" as p: pack-v2 as p2\n#p.pack-v2(scale: 1) #p.draw.text() #p2\n"
+        );
+        assert_eq!(
+            migrate("#import \"@preview/pack1:2.0.0\"\n#pack1.draw.content(anchor: 1)\n"),
+            "#import \"@preview/pack1:2.0.0\"\n#pack1.draw.text(align: 1)\n"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let mut migrations = Migrations::default();
+        assert!(
+            migrations
+                .extend_from_str("[[migration]]\npackage = \"pack1\"")
+                .is_err()
+        );
+        assert!(
+            migrations
+                .extend_from_str("[[migration]]\npackage = \"pack1\"\nsince = \"1.0\"")
+                .is_err()
+        );
+        assert!(
+            migrations
+                .extend_from_str(
+                    "[[migration]]\npackage = \"pack1\"\nsince = \"1.0.0\"\nrenames = {}"
+                )
+                .is_err()
+        );
+        assert!(migrations.extend_from_str("").is_ok());
+    }
+}
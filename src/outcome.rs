@@ -0,0 +1,195 @@
+use std::fmt::Display;
+
+use clap::ValueEnum;
+
+use crate::upgrade::Report;
+
+/// Exit code when something could not be checked or updated
+pub const ERRORS: u8 = 1;
+/// Exit code when compatible upgrades are available
+pub const COMPATIBLE: u8 = 73;
+/// Exit code when only incompatible upgrades are available
+pub const INCOMPATIBLE: u8 = 74;
+
+/// Which available upgrades make the run fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailOn {
+    Compatible,
+    Incompatible,
+    Any,
+}
+
+impl ValueEnum for FailOn {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Compatible, Self::Incompatible, Self::Any]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(match self {
+            Self::Compatible => clap::builder::PossibleValue::new("compatible"),
+            Self::Incompatible => clap::builder::PossibleValue::new("incompatible"),
+            Self::Any => clap::builder::PossibleValue::new("any"),
+        })
+    }
+}
+
+impl Display for FailOn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// What a whole run found, deciding its exit code
+#[derive(Debug, Default)]
+pub struct Outcome {
+    compatible: bool,
+    incompatible: bool,
+    changed: bool,
+    errors: bool,
+}
+
+impl Outcome {
+    /// Record the upgrades and issues of a checked file
+    ///
+    /// Upgrades refused because they would break imported items still count as available.
+    pub fn record(&mut self, report: &Report) {
+        let available = || report.upgrades.iter().chain(&report.refused);
+        self.compatible |= available().any(|upgrade| upgrade.is_compatible());
+        self.incompatible |=
+            report.has_incompat_versions || available().any(|upgrade| !upgrade.is_compatible());
+        self.changed |= !report.upgrades.is_empty();
+        self.errors |= !report.issues.is_empty();
+    }
+
+    pub fn error(&mut self) {
+        self.errors = true;
+    }
+
+    /// The exit code of the run
+    ///
+    /// Errors always fail the run. Otherwise, the available upgrades selected by `fail_on`
+    /// do; without it, a `dry_run` fails if files would change.
+    pub fn exit_code(&self, fail_on: Option<FailOn>, dry_run: bool) -> u8 {
+        if self.errors {
+            return ERRORS;
+        }
+        let fails = match fail_on {
+            None => dry_run && self.changed,
+            Some(FailOn::Compatible) => self.compatible,
+            Some(FailOn::Incompatible) => self.incompatible,
+            Some(FailOn::Any) => self.compatible || self.incompatible,
+        };
+        match (fails, self.compatible) {
+            (false, _) => 0,
+            (true, true) => COMPATIBLE,
+            (true, false) => INCOMPATIBLE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::upgrade::{Report, Upgrade};
+
+    use super::{COMPATIBLE, ERRORS, FailOn, INCOMPATIBLE, Outcome};
+
+    fn report(from: &str, to: &str, held_back: bool) -> Report {
+        let upgrade = Upgrade::new("", from, to);
+        let mut report = Report {
+            has_incompat_versions: held_back,
+            ..Default::default()
+        };
+        if held_back {
+            report.held_back.push(upgrade);
+        } else {
+            report.upgrades.push(upgrade);
+        }
+        report
+    }
+
+    #[test]
+    fn up_to_date() {
+        let outcome = Outcome::default();
+        for fail_on in [None, Some(FailOn::Compatible), Some(FailOn::Any)] {
+            assert_eq!(outcome.exit_code(fail_on, true), 0);
+        }
+    }
+
+    #[test]
+    fn compatible() {
+        let mut outcome = Outcome::default();
+        outcome.record(&report("@preview/a:1.0.0", "@preview/a:1.1.0", false));
+        assert_eq!(outcome.exit_code(None, false), 0);
+        assert_eq!(outcome.exit_code(None, true), COMPATIBLE);
+        assert_eq!(
+            outcome.exit_code(Some(FailOn::Compatible), false),
+            COMPATIBLE
+        );
+        assert_eq!(outcome.exit_code(Some(FailOn::Incompatible), true), 0);
+        assert_eq!(outcome.exit_code(Some(FailOn::Any), true), COMPATIBLE);
+
+        outcome.record(&report("@preview/b:1.0.0", "@preview/b:2.0.0", true));
+        assert_eq!(
+            outcome.exit_code(Some(FailOn::Incompatible), true),
+            COMPATIBLE
+        );
+    }
+
+    #[test]
+    fn only_incompatible() {
+        let mut outcome = Outcome::default();
+        outcome.record(&report("@preview/a:0.1.0", "@preview/a:0.2.0", true));
+        assert_eq!(outcome.exit_code(None, true), 0);
+        assert_eq!(outcome.exit_code(Some(FailOn::Compatible), true), 0);
+        assert_eq!(
+            outcome.exit_code(Some(FailOn::Incompatible), true),
+            INCOMPATIBLE
+        );
+        assert_eq!(outcome.exit_code(Some(FailOn::Any), true), INCOMPATIBLE);
+
+        // Applied with `--incompatible`
+        let mut outcome = Outcome::default();
+        outcome.record(&report("@preview/a:1.0.0", "@preview/a:2.0.0", false));
+        assert_eq!(outcome.exit_code(None, true), INCOMPATIBLE);
+        assert_eq!(outcome.exit_code(Some(FailOn::Any), true), INCOMPATIBLE);
+    }
+
+    #[test]
+    fn dry_run() {
+        let mut outcome = Outcome::default();
+        outcome.record(&report("@preview/a:1.0.0", "@preview/a:2.0.0", false));
+        assert_eq!(outcome.exit_code(None, false), 0);
+        assert_eq!(outcome.exit_code(None, true), INCOMPATIBLE);
+
+        outcome.record(&report("@preview/b:1.0.0", "@preview/b:1.1.0", false));
+        assert_eq!(outcome.exit_code(None, true), COMPATIBLE);
+    }
+
+    #[test]
+    fn refused() {
+        let mut outcome = Outcome::default();
+        let mut refused = Report::default();
+        refused
+            .refused
+            .append(&mut report("@preview/a:1.0.0", "@preview/a:1.1.0", false).upgrades);
+        outcome.record(&refused);
+        assert_eq!(outcome.exit_code(None, true), 0);
+        assert_eq!(
+            outcome.exit_code(Some(FailOn::Compatible), true),
+            COMPATIBLE
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut outcome = Outcome::default();
+        outcome.record(&report("@preview/a:1.0.0", "@preview/a:1.1.0", false));
+        outcome.error();
+        for fail_on in [None, Some(FailOn::Compatible), Some(FailOn::Incompatible)] {
+            assert_eq!(outcome.exit_code(fail_on, true), ERRORS);
+        }
+    }
+}
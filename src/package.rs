@@ -0,0 +1,58 @@
+use std::{env, path::PathBuf};
+
+use typst_syntax::package::PackageSpec;
+
+/// Directories where Typst keeps local and downloaded packages, in lookup order
+///
+/// Like the Typst CLI, `TYPST_PACKAGE_PATH` and `TYPST_PACKAGE_CACHE_PATH` override the
+/// default data and cache directories.
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub fn package_roots() -> Vec<PathBuf> {
+    let data = env::var_os("TYPST_PACKAGE_PATH")
+        .map(PathBuf::from)
+        .or_else(|| dirs::data_dir().map(|dir| dir.join("typst").join("packages")));
+    let cache = env::var_os("TYPST_PACKAGE_CACHE_PATH")
+        .map(PathBuf::from)
+        .or_else(|| dirs::cache_dir().map(|dir| dir.join("typst").join("packages")));
+    data.into_iter().chain(cache).collect()
+}
+
+/// Find the directory of `package` under the first of `roots` that contains it
+pub fn find_package_dir(roots: &[PathBuf], package: &PackageSpec) -> Option<PathBuf> {
+    roots
+        .iter()
+        .map(|root| {
+            root.join(package.namespace.as_str())
+                .join(package.name.as_str())
+                .join(package.version.to_string())
+        })
+        .find(|dir| dir.is_dir())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, str::FromStr};
+
+    use typst_syntax::package::PackageSpec;
+
+    use super::find_package_dir;
+
+    #[test]
+    fn find_in_roots() {
+        let roots = [
+            Path::new("non-existent-dir").to_path_buf(),
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests")
+                .join("packages"),
+        ];
+
+        let package = PackageSpec::from_str("@preview/pack1:2.0.0").unwrap();
+        assert_eq!(
+            find_package_dir(&roots, &package).unwrap(),
+            roots[1].join("preview").join("pack1").join("2.0.0")
+        );
+
+        let package = PackageSpec::from_str("@preview/pack1:0.1.0").unwrap();
+        assert!(find_package_dir(&roots, &package).is_none());
+    }
+}
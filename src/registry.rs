@@ -0,0 +1,493 @@
+use std::{
+    error::Error,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+use reqwest::{
+    Certificate, Proxy, StatusCode,
+    blocking::{Client, RequestBuilder, Response},
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+};
+use serde::{Deserialize, Serialize};
+
+pub const INDEX_URL: &str = "https://packages.typst.org/preview/index.json";
+
+const INDEX_FILE: &str = "preview-index.json";
+const VALIDATORS_FILE: &str = "preview-index.validators.json";
+
+/// Response headers validating a cached index with a conditional request
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// Where the index is cached between runs
+pub fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("typst-upgrade"))
+}
+
+/// How registry requests are made
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub proxy: Option<String>,
+    /// PEM bundle of extra root certificates
+    pub ca_cert: Option<PathBuf>,
+    pub connect_timeout: Duration,
+    /// Timeout of a whole request, including connecting
+    pub timeout: Duration,
+    /// How often failed requests are retried
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each further one
+    pub backoff: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            proxy: None,
+            ca_cert: None,
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(60),
+            retries: 5,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Client for the package registry
+pub struct Registry {
+    client: Client,
+    config: Config,
+}
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// Configure the registry requests of this run
+pub fn init(config: Config) -> Result<(), String> {
+    let registry = Registry::new(config)?;
+    REGISTRY
+        .set(registry)
+        .map_err(|_| "Registry is already configured".to_string())
+}
+
+pub fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(|| Registry::new(Config::default()).expect("Invalid default config"))
+}
+
+impl Registry {
+    pub fn new(config: Config) -> Result<Self, String> {
+        let mut builder = Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.timeout);
+        if let Some(proxy) = &config.proxy {
+            let proxy = Proxy::all(proxy).map_err(|err| format!("Invalid proxy {proxy}: {err}"))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &config.ca_cert {
+            let pem = fs::read(path)
+                .map_err(|err| format!("Cannot read CA bundle {}: {err}", path.display()))?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .map_err(|err| format!("Invalid CA bundle {}: {err}", path.display()))?;
+            if certs.is_empty() {
+                return Err(format!("No certificates in CA bundle {}", path.display()));
+            }
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        let client = builder
+            .build()
+            .map_err(|err| format!("Cannot create HTTP client: {}", chain(&err)))?;
+        Ok(Self { client, config })
+    }
+
+    /// Fetch the package index at `url` and read it with `parse`
+    ///
+    /// With a `cache` directory, the last response is kept there with its `ETag` and
+    /// `Last-Modified` headers, so later runs only download the index if it has changed,
+    /// and fall back to the cached copy if the registry cannot be reached. Responses that
+    /// `parse` rejects are not cached.
+    pub fn fetch_index<T>(
+        &self,
+        url: &str,
+        cache: Option<&Path>,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let cached = cache.and_then(read_cache);
+        let validators = cached.as_ref().map(|(_, v)| v);
+
+        let now = Instant::now();
+        let resp = match self.send(url, validators) {
+            Ok(resp) => resp,
+            Err(err) => {
+                return match cached {
+                    Some((index, _)) => {
+                        warn!("Network": "{err}, using the cached package metadata");
+                        parse(&index)
+                    }
+                    None => Err(err),
+                };
+            }
+        };
+        let elapsed = now.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            warn!(
+                "Network": "Fetched typst package metadata in {}.{:03}s",
+                elapsed.as_secs(),
+                elapsed.subsec_millis(),
+            );
+        }
+        debug!(
+            "Network": "Fetched typst package metadata in {}ms ({})",
+            elapsed.as_millis(),
+            resp.status(),
+        );
+
+        match (resp.status(), cached) {
+            (StatusCode::NOT_MODIFIED, Some((index, _))) => {
+                debug!("Network": "Cached package metadata is up-to-date");
+                parse(&index)
+            }
+            (status, _) if status.is_success() => {
+                let validators = Validators {
+                    etag: header(&resp, ETAG),
+                    last_modified: header(&resp, LAST_MODIFIED),
+                };
+                let text = resp
+                    .text()
+                    .map_err(|err| format!("Cannot read {url}: {}", self.describe(&err)))?;
+                let index = parse(&text)?;
+                if let Some(cache) = cache
+                    && let Err(err) = write_cache(cache, &text, &validators)
+                {
+                    warn!("Cache": "Cannot cache package metadata in {}: {err}", cache.display());
+                }
+                Ok(index)
+            }
+            (status, _) => Err(format!("Cannot fetch {url}: HTTP {status}")),
+        }
+    }
+
+    /// Download `url`, failing on error statuses
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    pub fn download(&self, url: &str) -> Result<Response, String> {
+        let resp = self.send(url, None)?;
+        match resp.status() {
+            status if status.is_success() => Ok(resp),
+            status => Err(format!("Cannot fetch {url}: HTTP {status}")),
+        }
+    }
+
+    /// Send a request, retrying with exponential backoff on network and server errors
+    fn send(&self, url: &str, validators: Option<&Validators>) -> Result<Response, String> {
+        let mut attempt = 0;
+        loop {
+            let err = match self.request(url, validators).send() {
+                Ok(resp) if !resp.status().is_server_error() => return Ok(resp),
+                Ok(resp) => format!("HTTP {}", resp.status()),
+                Err(err) => self.describe(&err),
+            };
+            if attempt >= self.config.retries {
+                return Err(format!("Cannot fetch {url}: {err}"));
+            }
+            let delay = self.config.backoff * 2u32.saturating_pow(attempt);
+            attempt += 1;
+            warn!(
+                "Network": "{err}, retrying in {}ms... ({} attempts left)",
+                delay.as_millis(),
+                self.config.retries - attempt + 1,
+            );
+            thread::sleep(delay);
+        }
+    }
+
+    fn request(&self, url: &str, validators: Option<&Validators>) -> RequestBuilder {
+        let mut request = self.client.get(url);
+        if let Some(validators) = validators {
+            if let Some(etag) = &validators.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        request
+    }
+
+    /// Explain a failed request in terms of the setting that caused it
+    fn describe(&self, err: &reqwest::Error) -> String {
+        let config = &self.config;
+        let cause = chain(err);
+        if err.is_timeout() {
+            format!(
+                "Timed out (connect timeout {}s, request timeout {}s)",
+                config.connect_timeout.as_secs(),
+                config.timeout.as_secs(),
+            )
+        } else if cause.contains("certificate") {
+            match &config.ca_cert {
+                Some(path) => format!(
+                    "TLS certificate rejected, also with CA bundle {}: {cause}",
+                    path.display()
+                ),
+                None => format!("TLS certificate rejected, a CA bundle may be missing: {cause}"),
+            }
+        } else if err.is_connect() {
+            match &config.proxy {
+                Some(proxy) => format!("Cannot connect through proxy {proxy}: {cause}"),
+                None => format!("Cannot connect: {cause}"),
+            }
+        } else {
+            cause
+        }
+    }
+}
+
+/// The message of `err` followed by those of its sources
+fn chain(err: &dyn Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        let _ = write!(message, ": {err}");
+        source = err.source();
+    }
+    message
+}
+
+fn header(resp: &Response, name: reqwest::header::HeaderName) -> Option<String> {
+    resp.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+fn read_cache(dir: &Path) -> Option<(String, Validators)> {
+    let index = fs::read_to_string(dir.join(INDEX_FILE)).ok()?;
+    let validators = fs::read_to_string(dir.join(VALIDATORS_FILE))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default();
+    Some((index, validators))
+}
+
+/// Replace the cached index, writing the validators last so they never describe a
+/// different index
+fn write_cache(dir: &Path, index: &str, validators: &Validators) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let _ = fs::remove_file(dir.join(VALIDATORS_FILE));
+    for (name, content) in [
+        (INDEX_FILE, index.to_string()),
+        (VALIDATORS_FILE, serde_json::to_string(validators)?),
+    ] {
+        let mut temp = tempfile::Builder::new()
+            .prefix(".typst-upgrade")
+            .tempfile_in(dir)?;
+        temp.write_all(content.as_bytes())?;
+        temp.persist(dir.join(name)).map_err(|err| err.error)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        path::PathBuf,
+        thread,
+        time::Duration,
+    };
+
+    use super::{Config, INDEX_FILE, Registry, Validators};
+
+    fn registry(retries: u32) -> Registry {
+        Registry::new(Config {
+            retries,
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    /// Accept any JSON array as the index
+    fn array(text: &str) -> Result<String, String> {
+        serde_json::from_str::<Vec<serde_json::Value>>(text)
+            .map(|_| text.to_string())
+            .map_err(|err| err.to_string())
+    }
+
+    /// Serve one response per entry of `responses`, returning the received request headers
+    fn serve(responses: Vec<&'static str>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/preview/index.json",
+            listener.local_addr().unwrap()
+        );
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    request.push_str(&line.to_ascii_lowercase());
+                }
+                requests.push(request);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let registry = registry(0);
+        let cache = tempfile::tempdir().unwrap();
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nLast-Modified: Mon, 05 Oct 2026 10:00:00 GMT\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]",
+            "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nETag: \"v2\"\r\nContent-Length: 4\r\nConnection: close\r\n\r\n[{}]",
+        ]);
+
+        assert_eq!(
+            registry
+                .fetch_index(&url, Some(cache.path()), array)
+                .unwrap(),
+            "[]"
+        );
+        assert_eq!(
+            super::read_cache(cache.path()).unwrap().1,
+            Validators {
+                etag: Some("\"v1\"".into()),
+                last_modified: Some("Mon, 05 Oct 2026 10:00:00 GMT".into()),
+            }
+        );
+        assert_eq!(
+            registry
+                .fetch_index(&url, Some(cache.path()), array)
+                .unwrap(),
+            "[]"
+        );
+        assert_eq!(
+            registry
+                .fetch_index(&url, Some(cache.path()), array)
+                .unwrap(),
+            "[{}]"
+        );
+        assert_eq!(
+            std::fs::read_to_string(cache.path().join(INDEX_FILE)).unwrap(),
+            "[{}]"
+        );
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+        assert!(requests[1].contains("if-modified-since: mon, 05 oct 2026 10:00:00 gmt"));
+        assert!(requests[2].contains("if-none-match: \"v1\""));
+    }
+
+    #[test]
+    fn invalid_response() {
+        let registry = registry(0);
+        let cache = tempfile::tempdir().unwrap();
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]",
+            "HTTP/1.1 200 OK\r\nETag: \"v2\"\r\nContent-Length: 6\r\nConnection: close\r\n\r\n<html>",
+        ]);
+        assert_eq!(
+            registry
+                .fetch_index(&url, Some(cache.path()), array)
+                .unwrap(),
+            "[]"
+        );
+        assert!(
+            registry
+                .fetch_index(&url, Some(cache.path()), array)
+                .is_err()
+        );
+        let (index, validators) = super::read_cache(cache.path()).unwrap();
+        assert_eq!(index, "[]");
+        assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn without_cache() {
+        let registry = registry(0);
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]",
+            "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        assert_eq!(registry.fetch_index(&url, None, array).unwrap(), "[]");
+        assert_eq!(
+            registry.fetch_index(&url, None, array).unwrap_err(),
+            format!("Cannot fetch {url}: HTTP 500 Internal Server Error")
+        );
+        let requests = server.join().unwrap();
+        assert!(!requests[1].contains("if-none-match"));
+    }
+
+    #[test]
+    fn retries() {
+        let (url, server) = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]",
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let registry = registry(1);
+        assert_eq!(registry.fetch_index(&url, None, array).unwrap(), "[]");
+        // Client errors are not retried
+        assert_eq!(
+            registry.fetch_index(&url, None, array).unwrap_err(),
+            format!("Cannot fetch {url}: HTTP 404 Not Found")
+        );
+        assert_eq!(server.join().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn invalid_config() {
+        let err = Registry::new(Config {
+            proxy: Some("not a proxy".into()),
+            ..Default::default()
+        })
+        .err()
+        .unwrap();
+        assert!(err.starts_with("Invalid proxy not a proxy: "), "{err}");
+
+        let err = Registry::new(Config {
+            ca_cert: Some(PathBuf::from("missing.pem")),
+            ..Default::default()
+        })
+        .err()
+        .unwrap();
+        assert!(
+            err.starts_with("Cannot read CA bundle missing.pem: "),
+            "{err}"
+        );
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let err = Registry::new(Config {
+            ca_cert: Some(file.path().to_path_buf()),
+            ..Default::default()
+        })
+        .err()
+        .unwrap();
+        assert_eq!(
+            err,
+            format!("No certificates in CA bundle {}", file.path().display())
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::{collections::HashMap, str::FromStr};
+
+use typst_syntax::package::PackageVersion;
+
+/// A package superseded by another one
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rename {
+    /// Name of the replacement package in the same namespace
+    pub to: String,
+    /// The oldest version of the replacement package to upgrade to
+    pub from: Option<PackageVersion>,
+}
+
+/// Table of renamed and deprecated packages
+#[derive(Debug, Default)]
+pub struct Renames {
+    renames: HashMap<String, Rename>,
+    deprecated: HashMap<String, String>,
+}
+
+impl Renames {
+    /// The table shipped with `typst-upgrade`, see `src/renames.txt`
+    pub fn builtin() -> Self {
+        let mut renames = Self::default();
+        renames
+            .extend_from_str(include_str!("renames.txt"))
+            .expect("Invalid built-in renames");
+        renames
+    }
+
+    /// Add the entries of a renames file, later entries override earlier ones
+    ///
+    /// Each non-empty line not starting with `#` is either
+    /// `old-name -> new-name [from <version>]` or `old-name deprecated [message]`.
+    pub fn extend_from_str(&mut self, text: &str) -> Result<(), String> {
+        for (no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| format!("line {}: {reason}: `{line}`", no + 1);
+
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap().to_string();
+            match words.next() {
+                Some("->") => {
+                    let to = words
+                        .next()
+                        .ok_or_else(|| invalid("missing new package name"))?
+                        .to_string();
+                    let from = match (words.next(), words.next(), words.next()) {
+                        (None, _, _) => None,
+                        (Some("from"), Some(version), None) => Some(
+                            PackageVersion::from_str(version)
+                                .map_err(|err| invalid(&format!("invalid version ({err})")))?,
+                        ),
+                        _ => return Err(invalid("expected `from <version>`")),
+                    };
+                    self.renames.insert(name, Rename { to, from });
+                }
+                Some("deprecated") => {
+                    let message = words.collect::<Vec<_>>().join(" ");
+                    self.deprecated.insert(name, message);
+                }
+                _ => return Err(invalid("expected `->` or `deprecated`")),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn rename(&self, name: &str) -> Option<&Rename> {
+        self.renames.get(name)
+    }
+
+    /// The deprecation message of `name` if it is deprecated, which may be empty
+    pub fn deprecation(&self, name: &str) -> Option<&str> {
+        self.deprecated.get(name).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use typst_syntax::package::PackageVersion;
+
+    use super::{Rename, Renames};
+
+    #[test]
+    fn builtin() {
+        let renames = Renames::builtin();
+        assert!(renames.deprecation("tablex").is_some());
+    }
+
+    #[test]
+    fn extend() {
+        let mut renames = Renames::default();
+        renames
+            .extend_from_str(
+                "# comment\n\
+                 \n\
+                 old1 -> new1\n\
+                 old2 -> new2 from 1.2.0\n\
+                 old3 deprecated\n\
+                 old4   deprecated  use   `new4`  \n",
+            )
+            .unwrap();
+        assert_eq!(
+            renames.rename("old1"),
+            Some(&Rename {
+                to: "new1".into(),
+                from: None,
+            })
+        );
+        assert_eq!(
+            renames.rename("old2"),
+            Some(&Rename {
+                to: "new2".into(),
+                from: Some(PackageVersion::from_str("1.2.0").unwrap()),
+            })
+        );
+        assert!(renames.rename("old3").is_none());
+        assert_eq!(renames.deprecation("old3"), Some(""));
+        assert_eq!(renames.deprecation("old4"), Some("use `new4`"));
+        assert_eq!(renames.deprecation("old1"), None);
+
+        renames.extend_from_str("old1 -> new1b").unwrap();
+        assert_eq!(renames.rename("old1").unwrap().to, "new1b");
+    }
+
+    #[test]
+    fn invalid() {
+        for (text, reason) in [
+            ("old", "line 1: expected `->` or `deprecated`"),
+            ("\nold ->", "line 2: missing new package name"),
+            ("old -> new 1.0.0", "line 1: expected `from <version>`"),
+            ("old -> new from 1.0", "line 1: invalid version"),
+        ] {
+            let err = Renames::default().extend_from_str(text).unwrap_err();
+            assert!(err.starts_with(reason), "{err}");
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use std::{ops::Range, path::Path};
+
+use serde_json::{Value, json};
+
+use crate::{
+    format::line_col,
+    summary::Summary,
+    upgrade::{IssueKind, Report, Upgrade},
+};
+
+struct Rule {
+    id: &'static str,
+    level: &'static str,
+    description: &'static str,
+}
+
+const RULES: &[Rule] = &[
+    Rule {
+        id: "outdated-compatible",
+        level: "warning",
+        description: "A compatible version of the imported package is available",
+    },
+    Rule {
+        id: "outdated-incompatible",
+        level: "note",
+        description: "An incompatible version of the imported package is available",
+    },
+    Rule {
+        id: "breaking-upgrade",
+        level: "warning",
+        description: "A newer version of the imported package no longer exports the imported items",
+    },
+    Rule {
+        id: "renamed-package",
+        level: "warning",
+        description: "The imported package has been replaced by another package",
+    },
+    Rule {
+        id: "unknown-package",
+        level: "error",
+        description: "The imported package is not in the registry",
+    },
+    Rule {
+        id: "unknown-namespace",
+        level: "error",
+        description: "The namespace of the imported package is not supported",
+    },
+    Rule {
+        id: "registry-unavailable",
+        level: "error",
+        description: "The registry could not be reached to check the imported package",
+    },
+    Rule {
+        id: "yanked-version",
+        level: "error",
+        description: "The imported version is no longer in the registry",
+    },
+];
+
+/// A SARIF 2.1.0 log collecting the findings of all checked files
+#[derive(Default)]
+pub struct SarifLog {
+    results: Vec<Value>,
+}
+
+impl SarifLog {
+    /// Record the findings of `report` on `file`, whose content is `text`
+    pub fn add(&mut self, file: &Path, text: &str, report: &Report) {
+        let uri = uri(file);
+        let mut findings = report
+            .upgrades
+            .iter()
+            .map(|upgrade| {
+                let rule = if upgrade.is_rename() {
+                    "renamed-package"
+                } else if upgrade.is_compatible() {
+                    "outdated-compatible"
+                } else {
+                    "outdated-incompatible"
+                };
+                finding(rule, upgrade)
+            })
+            .chain(
+                report
+                    .held_back
+                    .iter()
+                    .map(|upgrade| finding("outdated-incompatible", upgrade)),
+            )
+            .chain(
+                report
+                    .refused
+                    .iter()
+                    .map(|upgrade| finding("breaking-upgrade", upgrade)),
+            )
+            .chain(report.issues.iter().map(|issue| {
+                let rule = match issue.kind {
+                    IssueKind::UnknownNamespace => "unknown-namespace",
+                    IssueKind::UnknownPackage => "unknown-package",
+                    IssueKind::RegistryUnavailable => "registry-unavailable",
+                    IssueKind::YankedVersion => "yanked-version",
+                };
+                (rule, issue.range.clone(), issue.describe())
+            }))
+            .collect::<Vec<_>>();
+        findings.sort_by_key(|(_, range, _)| range.start);
+
+        for (rule, range, message) in findings {
+            let index = RULES.iter().position(|r| r.id == rule).unwrap();
+            let (start_line, start_column) = line_col(text, range.start);
+            let (end_line, end_column) = line_col(text, range.end);
+            self.results.push(json!({
+                "ruleId": rule,
+                "ruleIndex": index,
+                "level": RULES[index].level,
+                "message": { "text": message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": uri },
+                        "region": {
+                            "startLine": start_line,
+                            "startColumn": start_column,
+                            "endLine": end_line,
+                            "endColumn": end_column,
+                        },
+                    },
+                }],
+            }));
+        }
+    }
+
+    /// The log, with `summary` in the properties of the run
+    pub fn to_json(&self, summary: &Summary) -> Value {
+        let rules = RULES
+            .iter()
+            .map(|rule| {
+                json!({
+                    "id": rule.id,
+                    "shortDescription": { "text": rule.description },
+                    "defaultConfiguration": { "level": rule.level },
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                        "rules": rules,
+                    },
+                },
+                // Columns count characters, as in the human-readable output
+                "columnKind": "unicodeCodePoints",
+                "results": self.results,
+                "properties": { "summary": summary.to_json() },
+            }],
+        })
+    }
+}
+
+fn finding(rule: &'static str, upgrade: &Upgrade) -> (&'static str, Range<usize>, String) {
+    (rule, upgrade.range.clone(), upgrade.describe())
+}
+
+/// Relative URI reference of `path`, percent-encoding anything but unreserved characters
+fn uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let path = path.strip_prefix("./").unwrap_or(&path);
+    let mut result = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            result.push(byte as char);
+        } else {
+            result.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeSet, path::Path, str::FromStr};
+
+    use serde_json::json;
+    use typst_syntax::package::PackageSpec;
+
+    use crate::upgrade::{Issue, IssueKind, Report, Upgrade};
+
+    use crate::summary::Summary;
+
+    use super::SarifLog;
+
+    #[test]
+    fn uri() {
+        assert_eq!(
+            super::uri(Path::new("./a b/ｌｉｂ.typ")),
+            "a%20b/%EF%BD%8C%EF%BD%89%EF%BD%82.typ"
+        );
+        assert_eq!(super::uri(Path::new("lib/main.typ")), "lib/main.typ");
+    }
+
+    /// Every declared rule is produced by some finding, so none is declared ahead of its use
+    #[test]
+    fn rules() {
+        let issue = |kind| Issue {
+            range: 0..0,
+            package: PackageSpec::from_str("@preview/pack1:1.0.0").unwrap(),
+            kind,
+        };
+        let report = Report {
+            has_incompat_versions: true,
+            upgrades: vec![
+                Upgrade::new("", "@preview/pack1:1.0.0", "@preview/pack1:1.1.0"),
+                Upgrade::new("", "@preview/pack1:1.0.0", "@preview/pack1:2.0.0"),
+                Upgrade::new("", "@preview/pack1:1.0.0", "@preview/pack2:1.0.0"),
+            ],
+            held_back: vec![Upgrade::new(
+                "",
+                "@preview/pack1:1.0.0",
+                "@preview/pack1:2.0.0",
+            )],
+            refused: vec![Upgrade::new(
+                "",
+                "@preview/pack1:1.0.0",
+                "@preview/pack1:1.1.0",
+            )],
+            issues: vec![
+                issue(IssueKind::UnknownNamespace),
+                issue(IssueKind::UnknownPackage),
+                issue(IssueKind::RegistryUnavailable),
+                issue(IssueKind::YankedVersion),
+            ],
+        };
+        let mut log = SarifLog::default();
+        log.add(Path::new("main.typ"), "", &report);
+
+        let produced = log
+            .results
+            .iter()
+            .map(|result| result["ruleId"].as_str().unwrap())
+            .collect::<BTreeSet<_>>();
+        let declared = super::RULES
+            .iter()
+            .map(|rule| rule.id)
+            .collect::<BTreeSet<_>>();
+        assert_eq!(produced, declared);
+    }
+
+    #[test]
+    fn sarif_log() {
+        let text = "#import \"@preview/pack1:1.0.0\"\n#import \"@preview/pack2:0.1.0\"\n#import \"@other/pack3:1.0.0\"";
+        let spec = |s| PackageSpec::from_str(s).unwrap();
+        let report = Report {
+            has_incompat_versions: true,
+            upgrades: vec![Upgrade::new(
+                text,
+                "@preview/pack1:1.0.0",
+                "@preview/pack1:1.1.0",
+            )],
+            held_back: vec![Upgrade::new(
+                text,
+                "@preview/pack2:0.1.0",
+                "@preview/pack2:0.2.0",
+            )],
+            refused: Vec::new(),
+            issues: vec![Issue {
+                range: 70..90,
+                package: spec("@other/pack3:1.0.0"),
+                kind: IssueKind::UnknownNamespace,
+            }],
+        };
+        let mut log = SarifLog::default();
+        log.add(Path::new("./main.typ"), text, &report);
+        let log = log.to_json(&Summary::default());
+
+        assert_eq!(log["version"], "2.1.0");
+        let run = &log["runs"][0];
+        assert_eq!(run["properties"]["summary"]["filesScanned"], 0);
+        assert_eq!(run["tool"]["driver"]["rules"].as_array().unwrap().len(), 8);
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| (
+                    result["ruleId"].as_str().unwrap(),
+                    result["level"].as_str().unwrap()
+                ))
+                .collect::<Vec<_>>(),
+            [
+                ("outdated-compatible", "warning"),
+                ("outdated-incompatible", "note"),
+                ("unknown-namespace", "error"),
+            ]
+        );
+        assert_eq!(
+            results[0]["message"]["text"],
+            "@preview/pack1 1.0.0 can be upgraded to 1.1.0"
+        );
+        assert_eq!(
+            results[2]["locations"][0]["physicalLocation"],
+            json!({
+                "artifactLocation": { "uri": "main.typ" },
+                "region": { "startLine": 3, "startColumn": 9, "endLine": 3, "endColumn": 29 },
+            })
+        );
+        assert_eq!(
+            run["tool"]["driver"]["rules"][results[2]["ruleIndex"].as_u64().unwrap() as usize]["id"],
+            "unknown-namespace"
+        );
+    }
+}
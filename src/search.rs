@@ -0,0 +1,174 @@
+use std::{fmt::Write as _, io::Write as _, process::ExitCode, str::FromStr};
+
+use typst_syntax::package::{PackageVersion, VersionBound};
+
+use crate::index::{Entry, Index, registry_index};
+
+/// Parse a compiler version like `0.12` or `0.12.0`
+pub fn parse_compiler(s: &str) -> Result<PackageVersion, String> {
+    let bound = VersionBound::from_str(s).map_err(|err| err.to_string())?;
+    Ok(PackageVersion {
+        major: bound.major,
+        minor: bound.minor.unwrap_or(0),
+        patch: bound.patch.unwrap_or(0),
+    })
+}
+
+/// Print the packages of the registry matching `query`, with an import line for each
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub fn run(query: &str, category: Option<&str>, compiler: Option<PackageVersion>) -> ExitCode {
+    let Some(index) = registry_index() else {
+        return ExitCode::FAILURE;
+    };
+    let results = search(index, query, category, compiler);
+    if results.is_empty() {
+        warn!("Search": "No package matches {query:?}");
+        return ExitCode::SUCCESS;
+    }
+    crate::term::stdout()
+        .write_all(describe(&results).as_bytes())
+        .expect("Cannot write to stdout");
+    ExitCode::SUCCESS
+}
+
+/// The latest version of each package matching `query`, in its name, description,
+/// keywords or categories, best matches first
+///
+/// With `category`, only packages in that category match. With `compiler`, the latest
+/// version supporting that compiler is taken, skipping packages without one.
+fn search<'a>(
+    index: &'a Index,
+    query: &str,
+    category: Option<&str>,
+    compiler: Option<PackageVersion>,
+) -> Vec<&'a Entry> {
+    let query = query.to_lowercase();
+    let contains = |text: &str| text.to_lowercase().contains(&query);
+
+    let mut results = index
+        .packages
+        .values()
+        .filter_map(|entries| {
+            entries
+                .iter()
+                .filter(|entry| {
+                    compiler.is_none_or(|compiler| {
+                        entry
+                            .compiler
+                            .as_ref()
+                            .is_none_or(|bound| compiler.matches_ge(bound))
+                    })
+                })
+                .max_by_key(|entry| entry.version)
+        })
+        .filter(|entry| {
+            category.is_none_or(|category| {
+                entry
+                    .categories
+                    .iter()
+                    .any(|c| c.eq_ignore_ascii_case(category))
+            })
+        })
+        .filter_map(|entry| {
+            let rank = if entry.name.to_lowercase() == query {
+                0
+            } else if contains(&entry.name) {
+                1
+            } else if entry
+                .keywords
+                .iter()
+                .chain(&entry.categories)
+                .any(|k| contains(k))
+                || entry.description.as_deref().is_some_and(contains)
+            {
+                2
+            } else {
+                return None;
+            };
+            Some((rank, entry))
+        })
+        .collect::<Vec<_>>();
+    results.sort_by(|(a_rank, a), (b_rank, b)| a_rank.cmp(b_rank).then(a.name.cmp(&b.name)));
+    results.into_iter().map(|(_, entry)| entry).collect()
+}
+
+fn describe(results: &[&Entry]) -> String {
+    let mut text = String::new();
+    for entry in results {
+        let _ = write!(text, "{} {}", entry.name, entry.version);
+        if let Some(description) = &entry.description {
+            let _ = write!(text, "  {description}");
+        }
+        let _ = writeln!(
+            text,
+            "\n    #import \"@preview/{}:{}\"",
+            entry.name, entry.version
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use typst_syntax::package::PackageVersion;
+
+    use crate::index::Index;
+
+    fn index() -> Index {
+        Index::parse(
+            r#"[
+                {"name": "cetz", "version": "0.2.2", "description": "Drawing with Typst", "categories": ["visualization"]},
+                {"name": "cetz", "version": "0.3.1", "compiler": "0.12.0", "description": "Drawing with Typst", "categories": ["visualization"]},
+                {"name": "cetz-plot", "version": "0.1.0", "compiler": "0.12.0", "keywords": ["chart"], "categories": ["visualization"]},
+                {"name": "fletcher", "version": "0.5.1", "description": "Diagrams with cetz", "categories": ["visualization"]},
+                {"name": "touying", "version": "0.5.3", "description": "Slides", "categories": ["presentation"]}
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    fn find(query: &str, category: Option<&str>, compiler: Option<&str>) -> Vec<String> {
+        let index = index();
+        let compiler = compiler.map(|compiler| super::parse_compiler(compiler).unwrap());
+        super::search(&index, query, category, compiler)
+            .into_iter()
+            .map(|entry| format!("{}:{}", entry.name, entry.version))
+            .collect()
+    }
+
+    #[test]
+    fn parse_compiler() {
+        assert_eq!(
+            super::parse_compiler("0.12"),
+            Ok(PackageVersion::from_str("0.12.0").unwrap())
+        );
+        assert!(super::parse_compiler("latest").is_err());
+    }
+
+    #[test]
+    fn search() {
+        assert_eq!(
+            find("CeTZ", None, None),
+            ["cetz:0.3.1", "cetz-plot:0.1.0", "fletcher:0.5.1"]
+        );
+        assert_eq!(find("chart", None, None), ["cetz-plot:0.1.0"]);
+        assert_eq!(find("", Some("Presentation"), None), ["touying:0.5.3"]);
+        assert_eq!(
+            find("cetz", None, Some("0.11")),
+            ["cetz:0.2.2", "fletcher:0.5.1"]
+        );
+        assert!(find("unknown", None, None).is_empty());
+    }
+
+    #[test]
+    fn describe() {
+        let index = index();
+        let results = super::search(&index, "touying", None, None);
+        assert_eq!(
+            super::describe(&results),
+            "touying 0.5.3  Slides\n    #import \"@preview/touying:0.5.3\"\n"
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use std::{collections::BTreeMap, fmt::Write as _, io::Write as _};
+
+use serde_json::{Value, json};
+
+use crate::upgrade::{Report, Upgrade};
+
+/// What happened to the imports of a package version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    /// Rewritten in the files
+    Upgraded,
+    /// Not written, e.g. in a dry run
+    Available,
+    /// Only incompatible versions are available
+    HeldBack,
+    /// Not written because the new version would break imported items
+    Refused,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Upgraded => "upgraded",
+            Self::Available => "available",
+            Self::HeldBack => "held back",
+            Self::Refused => "refused",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Row {
+    package: String,
+    from: String,
+    to: String,
+    status: Status,
+}
+
+/// Aggregate of a whole run, printed at its end
+#[derive(Debug, Default)]
+pub struct Summary {
+    files_scanned: usize,
+    files_outdated: usize,
+    files_changed: usize,
+    errors: usize,
+    /// Number of imports of each upgrade
+    rows: BTreeMap<Row, usize>,
+}
+
+impl Summary {
+    /// Record the report of a checked file, whose upgrades were `written` or not
+    pub fn record(&mut self, report: &Report, written: bool) {
+        self.files_scanned += 1;
+        self.errors += report.issues.len();
+        if !report.upgrades.is_empty() {
+            self.files_outdated += 1;
+            self.files_changed += usize::from(written);
+        }
+        let status = if written {
+            Status::Upgraded
+        } else {
+            Status::Available
+        };
+        for upgrade in &report.upgrades {
+            *self.rows.entry(Self::row(upgrade, status)).or_default() += 1;
+        }
+        for upgrade in &report.held_back {
+            *self
+                .rows
+                .entry(Self::row(upgrade, Status::HeldBack))
+                .or_default() += 1;
+        }
+        for upgrade in &report.refused {
+            *self
+                .rows
+                .entry(Self::row(upgrade, Status::Refused))
+                .or_default() += 1;
+        }
+    }
+
+    /// Record a file that could not be checked
+    pub fn skipped(&mut self) {
+        self.files_scanned += 1;
+        self.errors += 1;
+    }
+
+    pub fn error(&mut self) {
+        self.errors += 1;
+    }
+
+    fn row(upgrade: &Upgrade, status: Status) -> Row {
+        let Upgrade { from, to, .. } = upgrade;
+        Row {
+            package: format!("@{}/{}", from.namespace, from.name),
+            from: from.version.to_string(),
+            to: if upgrade.is_rename() {
+                to.to_string()
+            } else {
+                to.version.to_string()
+            },
+            status,
+        }
+    }
+
+    fn headline(&self) -> String {
+        format!(
+            "{} file(s) scanned, {} outdated, {} changed, {} error(s)",
+            self.files_scanned, self.files_outdated, self.files_changed, self.errors
+        )
+    }
+
+    /// The summary as an aligned table
+    pub fn table(&self) -> String {
+        let mut table = format!("{:>12} {}\n", "Summary", self.headline());
+        if self.rows.is_empty() {
+            return table;
+        }
+
+        let header = ["Package", "From", "To", "Imports", "Status"];
+        let cells = self
+            .rows
+            .iter()
+            .map(|(row, count)| {
+                [
+                    row.package.clone(),
+                    row.from.clone(),
+                    row.to.clone(),
+                    count.to_string(),
+                    row.status.name().to_string(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let mut widths = header.map(|cell| cell.chars().count());
+        for line in &cells {
+            for (width, cell) in widths.iter_mut().zip(line) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let lines = std::iter::once(header.map(str::to_string)).chain(cells);
+        for line in lines {
+            let mut text = format!("{:>12}", "");
+            for (cell, width) in line.iter().zip(widths) {
+                let _ = write!(text, " {cell:<width$} ");
+            }
+            table.push_str(text.trim_end());
+            table.push('\n');
+        }
+        table
+    }
+
+    /// The summary as Markdown, e.g. for a GitHub Actions job summary
+    pub fn markdown(&self) -> String {
+        let mut markdown = format!("### typst-upgrade\n\n{}\n", self.headline());
+        if self.rows.is_empty() {
+            return markdown;
+        }
+        markdown.push_str("\n| Package | From | To | Imports | Status |\n");
+        markdown.push_str("| ------- | ---- | -- | ------- | ------ |\n");
+        for (row, count) in &self.rows {
+            let _ = writeln!(
+                markdown,
+                "| `{}` | {} | {} | {count} | {} |",
+                row.package,
+                row.from,
+                row.to,
+                row.status.name()
+            );
+        }
+        markdown
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "filesScanned": self.files_scanned,
+            "filesOutdated": self.files_outdated,
+            "filesChanged": self.files_changed,
+            "errors": self.errors,
+            "packages": self.rows.iter().map(|(row, count)| json!({
+                "package": row.package,
+                "from": row.from,
+                "to": row.to,
+                "imports": count,
+                "status": row.status.name(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Name and value pairs, e.g. for the properties of a JUnit test suite
+    pub fn properties(&self) -> Vec<(String, String)> {
+        let mut properties = vec![
+            ("files-scanned".into(), self.files_scanned.to_string()),
+            ("files-outdated".into(), self.files_outdated.to_string()),
+            ("files-changed".into(), self.files_changed.to_string()),
+            ("errors".into(), self.errors.to_string()),
+        ];
+        for (row, count) in &self.rows {
+            properties.push((
+                row.status.name().replace(' ', "-"),
+                format!(
+                    "{} {} -> {} ({count} import(s))",
+                    row.package, row.from, row.to
+                ),
+            ));
+        }
+        properties
+    }
+
+    /// Print the table regardless of the verbosity level
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    pub fn show(&self) {
+        crate::term::stdout()
+            .write_all(self.table().as_bytes())
+            .expect("Cannot write to stdout");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use typst_syntax::package::PackageSpec;
+
+    use crate::upgrade::{Issue, IssueKind, Report, Upgrade};
+
+    use super::Summary;
+
+    fn summary() -> Summary {
+        let mut summary = Summary::default();
+        summary.record(
+            &Report {
+                has_incompat_versions: true,
+                upgrades: vec![Upgrade::new(
+                    "",
+                    "@preview/cetz:0.2.1",
+                    "@preview/cetz:0.3.1",
+                )],
+                held_back: vec![Upgrade::new(
+                    "",
+                    "@preview/pack2:0.1.0",
+                    "@preview/pack2:2.0.0",
+                )],
+                ..Default::default()
+            },
+            true,
+        );
+        summary.record(
+            &Report {
+                upgrades: vec![
+                    Upgrade::new("", "@preview/cetz:0.2.1", "@preview/cetz:0.3.1"),
+                    Upgrade::new("", "@preview/old:1.0.0", "@preview/new:2.0.0"),
+                ],
+                issues: vec![Issue {
+                    range: 0..0,
+                    package: PackageSpec::from_str("@preview/unknown:1.0.0").unwrap(),
+                    kind: IssueKind::UnknownPackage,
+                }],
+                ..Default::default()
+            },
+            true,
+        );
+        summary.record(&Report::default(), false);
+        summary.skipped();
+        summary
+    }
+
+    #[test]
+    fn table() {
+        assert_eq!(
+            summary().table(),
+            "     Summary 4 file(s) scanned, 2 outdated, 2 changed, 2 error(s)
+             Package         From   To                  Imports  Status
+             @preview/cetz   0.2.1  0.3.1               2        upgraded
+             @preview/old    1.0.0  @preview/new:2.0.0  1        upgraded
+             @preview/pack2  0.1.0  2.0.0               1        held back
+"
+        );
+        assert_eq!(
+            Summary::default().table(),
+            "     Summary 0 file(s) scanned, 0 outdated, 0 changed, 0 error(s)\n"
+        );
+    }
+
+    #[test]
+    fn markdown() {
+        assert_eq!(
+            summary().markdown(),
+            "### typst-upgrade
+
+4 file(s) scanned, 2 outdated, 2 changed, 2 error(s)
+
+| Package | From | To | Imports | Status |
+| ------- | ---- | -- | ------- | ------ |
+| `@preview/cetz` | 0.2.1 | 0.3.1 | 2 | upgraded |
+| `@preview/old` | 1.0.0 | @preview/new:2.0.0 | 1 | upgraded |
+| `@preview/pack2` | 0.1.0 | 2.0.0 | 1 | held back |
+"
+        );
+    }
+
+    #[test]
+    fn to_json() {
+        let json = summary().to_json();
+        assert_eq!(json["filesScanned"], 4);
+        assert_eq!(json["packages"][2]["status"], "held back");
+        assert_eq!(json["packages"][0]["imports"], 2);
+    }
+
+    #[test]
+    fn properties() {
+        let properties = summary().properties();
+        assert_eq!(properties[0], ("files-scanned".into(), "4".into()));
+        assert_eq!(
+            properties[6],
+            (
+                "held-back".into(),
+                "@preview/pack2 0.1.0 -> 2.0.0 (1 import(s))".into()
+            )
+        );
+    }
+}
@@ -1,10 +1,27 @@
+use std::cell::Cell;
 use std::fmt::Arguments;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 use std::sync::OnceLock;
 
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 static COLOR_CHOICE: OnceLock<ColorChoice> = OnceLock::new();
+static LEVEL: OnceLock<Level> = OnceLock::new();
+static STDOUT_REDIRECTED: OnceLock<bool> = OnceLock::new();
+
+thread_local! {
+    static SILENCED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// How much is printed, each level including the ones before
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Only errors and the final summary
+    Quiet,
+    Normal,
+    Verbose,
+    Debug,
+}
 
 pub fn init(color: clap::ColorChoice) {
     COLOR_CHOICE
@@ -20,6 +37,62 @@ pub fn color_choice() -> ColorChoice {
     *COLOR_CHOICE.get().unwrap_or(&ColorChoice::Auto)
 }
 
+pub fn init_level(level: Level) {
+    LEVEL.set(level).unwrap();
+}
+
+/// Whether messages of `level` are printed
+pub fn enabled(level: Level) -> bool {
+    !SILENCED.get() && *LEVEL.get().unwrap_or(&Level::Normal) >= level
+}
+
+/// Run `f` without printing anything on this thread, errors included
+pub fn silenced<T>(f: impl FnOnce() -> T) -> T {
+    let silenced = SILENCED.replace(true);
+    let result = f();
+    SILENCED.set(silenced);
+    result
+}
+
+/// Print the human-readable output meant for stdout to stderr, leaving stdout to a
+/// machine-readable report
+pub fn redirect_stdout() {
+    STDOUT_REDIRECTED.set(true).unwrap();
+}
+
+fn stdout_redirected() -> bool {
+    *STDOUT_REDIRECTED.get().unwrap_or(&false)
+}
+
+/// Whether the human-readable output meant for stdout goes to a terminal
+pub fn stdout_is_terminal() -> bool {
+    if stdout_redirected() {
+        std::io::stderr().is_terminal()
+    } else {
+        std::io::stdout().is_terminal()
+    }
+}
+
+/// Stream for the human-readable output meant for stdout
+pub fn stdout() -> StandardStream {
+    if stdout_redirected() {
+        return stderr();
+    }
+    StandardStream::stdout(if std::io::stdout().is_terminal() {
+        color_choice()
+    } else {
+        ColorChoice::Never
+    })
+}
+
+pub fn stderr() -> StandardStream {
+    StandardStream::stderr(if std::io::stderr().is_terminal() {
+        color_choice()
+    } else {
+        ColorChoice::Never
+    })
+}
+
 #[cfg_attr(coverage_nightly, coverage(off))]
 pub fn term_println(
     mut stream: StandardStream,
@@ -43,30 +116,12 @@ pub fn term_println(
 
 macro_rules! __term_println {
     (@COLOR_MOTION $stream:ident, $color:expr, $motion:literal, $($args:tt)*) => {
-        use std::io::IsTerminal;
-        use termcolor::{ColorChoice, StandardStream};
-
-        let stream = StandardStream::$stream(if std::io::$stream().is_terminal() {
-            $crate::term::color_choice()
-        } else {
-            ColorChoice::Never
-        });
-
-        $crate::term::term_println(stream, $color, false, $motion, format_args!($($args)*))
+        $crate::term::term_println($crate::term::$stream(), $color, false, $motion, format_args!($($args)*))
             .expect(&format!("Cannot write to {}", stringify!($stream)));
     };
 
     (@COLOR_WHOLE_LINE $stream:ident, $color:expr, $motion:literal, $($args:tt)*) => {
-        use std::io::IsTerminal;
-        use termcolor::{ColorChoice, StandardStream};
-
-        let stream = StandardStream::$stream(if std::io::$stream().is_terminal() {
-            $crate::term::color_choice()
-        } else {
-            ColorChoice::Never
-        });
-
-        $crate::term::term_println(stream, $color, true, $motion, format_args!($($args)*))
+        $crate::term::term_println($crate::term::$stream(), $color, true, $motion, format_args!($($args)*))
             .expect(&format!("Cannot write to {}", stringify!($stream)));
     };
 }
@@ -74,17 +129,47 @@ macro_rules! __term_println {
 #[macro_export]
 macro_rules! info {
     ($motion:literal: $($args:tt)*) => {
-        __term_println!(@COLOR_MOTION stdout, Some(termcolor::Color::Green), $motion, $($args)*)
+        if $crate::term::enabled($crate::term::Level::Normal) {
+            __term_println!(@COLOR_MOTION stdout, Some(termcolor::Color::Green), $motion, $($args)*);
+        }
     };
     ($($args:tt)*) => {
         info!("INFO": $($args)*)
     };
 }
 
+/// Details printed with `-v`
+#[macro_export]
+macro_rules! verbose {
+    ($motion:literal: $($args:tt)*) => {
+        if $crate::term::enabled($crate::term::Level::Verbose) {
+            __term_println!(@COLOR_MOTION stdout, Some(termcolor::Color::Cyan), $motion, $($args)*);
+        }
+    };
+    ($($args:tt)*) => {
+        verbose!("NOTE": $($args)*)
+    };
+}
+
+/// Internals printed with `-vv`
+#[macro_export]
+macro_rules! debug {
+    ($motion:literal: $($args:tt)*) => {
+        if $crate::term::enabled($crate::term::Level::Debug) {
+            __term_println!(@COLOR_MOTION stderr, Some(termcolor::Color::Magenta), $motion, $($args)*);
+        }
+    };
+    ($($args:tt)*) => {
+        debug!("DEBUG": $($args)*)
+    };
+}
+
 #[macro_export]
 macro_rules! warn {
     ($motion:literal: $($args:tt)*) => {
-        __term_println!(@COLOR_MOTION stderr, Some(termcolor::Color::Yellow), $motion, $($args)*)
+        if $crate::term::enabled($crate::term::Level::Normal) {
+            __term_println!(@COLOR_MOTION stderr, Some(termcolor::Color::Yellow), $motion, $($args)*);
+        }
     };
     ($($args:tt)*) => {
         warn!("WARN": $($args)*)
@@ -94,7 +179,9 @@ macro_rules! warn {
 #[macro_export]
 macro_rules! error {
     ($motion:literal: $($args:tt)*) => {
-        __term_println!(@COLOR_MOTION stderr, Some(termcolor::Color::Red), $motion, $($args)*)
+        if $crate::term::enabled($crate::term::Level::Quiet) {
+            __term_println!(@COLOR_MOTION stderr, Some(termcolor::Color::Red), $motion, $($args)*);
+        }
     };
     ($($args:tt)*) => {
         error!("ERROR": $($args)*)
@@ -104,13 +191,19 @@ macro_rules! error {
 #[macro_export]
 macro_rules! diff {
     (del $($args:tt)*) => {
-        __term_println!(@COLOR_WHOLE_LINE stdout, Some(termcolor::Color::Red), "-", $($args)*)
+        if $crate::term::enabled($crate::term::Level::Normal) {
+            __term_println!(@COLOR_WHOLE_LINE stdout, Some(termcolor::Color::Red), "-", $($args)*);
+        }
     };
     (add $($args:tt)*) => {
-        __term_println!(@COLOR_WHOLE_LINE stdout, Some(termcolor::Color::Green), "+", $($args)*)
+        if $crate::term::enabled($crate::term::Level::Normal) {
+            __term_println!(@COLOR_WHOLE_LINE stdout, Some(termcolor::Color::Green), "+", $($args)*);
+        }
     };
     ($($args:tt)*) => {
-        __term_println!(@COLOR_WHOLE_LINE stdout, None, "", $($args)*)
+        if $crate::term::enabled($crate::term::Level::Normal) {
+            __term_println!(@COLOR_WHOLE_LINE stdout, None, "", $($args)*);
+        }
     };
 }
 
@@ -118,6 +211,25 @@ macro_rules! diff {
 mod test {
     use termcolor::ColorChoice;
 
+    #[test]
+    fn level() {
+        assert!(super::enabled(super::Level::Normal));
+        assert!(!super::enabled(super::Level::Verbose));
+        assert!(super::Level::Quiet < super::Level::Normal);
+        assert!(super::Level::Verbose < super::Level::Debug);
+    }
+
+    #[test]
+    fn silenced() {
+        assert!(super::enabled(super::Level::Quiet));
+        // Still silenced after a nested scope ends
+        assert!(!super::silenced(|| {
+            super::silenced(|| ());
+            super::enabled(super::Level::Quiet)
+        }));
+        assert!(super::enabled(super::Level::Quiet));
+    }
+
     #[test]
     fn init() {
         super::init(clap::ColorChoice::Auto);
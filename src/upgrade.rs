@@ -1,249 +1,664 @@
 use std::{
-    collections::HashMap,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::PathBuf,
+    rc::Rc,
     str::FromStr,
-    time::{Duration, Instant},
 };
 
-use once_cell::sync::Lazy;
 use typst_syntax::{
     SyntaxKind, SyntaxNode,
-    ast::{AstNode, Expr, ModuleImport},
+    ast::{AstNode, Expr, Imports, ModuleImport},
     package::{PackageSpec, PackageVersion},
 };
 
+use crate::{
+    constraint::Constraints,
+    exports, index, package,
+    rename::{Rename, Renames},
+};
+
+/// What a conversion found and changed
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Whether some packages have incompatible versions that were not applied
+    pub has_incompat_versions: bool,
+    /// Upgrades applied to the syntax tree, in source order
+    pub upgrades: Vec<Upgrade>,
+    /// Incompatible upgrades available but not applied, in source order
+    pub held_back: Vec<Upgrade>,
+    /// Upgrades not applied because they would break imported items, in source order
+    pub refused: Vec<Upgrade>,
+    /// Imports that cannot be checked against the registry, in source order
+    pub issues: Vec<Issue>,
+}
+
+/// A single package import rewritten (or to be rewritten) from one version to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upgrade {
+    /// Byte range of the import string, including quotes, in the original source
+    pub range: Range<usize>,
+    pub from: PackageSpec,
+    pub to: PackageSpec,
+}
+
+impl Upgrade {
+    /// Whether the import is replaced by another package
+    pub fn is_rename(&self) -> bool {
+        self.from.name != self.to.name
+    }
+
+    /// Whether the new version is semver-compatible with the old one
+    pub fn is_compatible(&self) -> bool {
+        !self.is_rename()
+            && self.from.version.major != 0
+            && self.from.version.major == self.to.version.major
+    }
+
+    /// Describe the upgrade, e.g. `@preview/cetz 0.2.1 can be upgraded to 0.3.1`
+    pub fn describe(&self) -> String {
+        let Upgrade { from, to, .. } = self;
+        if self.is_rename() {
+            format!(
+                "@{}/{} {} can be replaced by {to}",
+                from.namespace, from.name, from.version
+            )
+        } else if to.version < from.version {
+            format!(
+                "@{}/{} {} is yanked and can be downgraded to {}",
+                from.namespace, from.name, from.version, to.version
+            )
+        } else {
+            format!(
+                "@{}/{} {} can be upgraded to {}",
+                from.namespace, from.name, from.version, to.version
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+impl Upgrade {
+    /// The upgrade of the import of `from` in `text`, or at its start if not imported there
+    pub fn new(text: &str, from: &str, to: &str) -> Self {
+        let range = text
+            .find(&format!("\"{from}\""))
+            .map_or(0..0, |start| start..start + from.len() + 2);
+        Self {
+            range,
+            from: PackageSpec::from_str(from).unwrap(),
+            to: PackageSpec::from_str(to).unwrap(),
+        }
+    }
+}
+
+/// An imported package that cannot be checked against the registry, or whose version is gone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// Byte range of the import string, including quotes, in the original source
+    pub range: Range<usize>,
+    pub package: PackageSpec,
+    pub kind: IssueKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IssueKind {
+    UnknownNamespace,
+    UnknownPackage,
+    RegistryUnavailable,
+    /// The package is known, but the imported version is no longer in the registry
+    YankedVersion,
+}
+
+impl Issue {
+    pub fn describe(&self) -> String {
+        self.kind.describe(&self.package)
+    }
+}
+
+impl IssueKind {
+    /// Describe the issue with an import of `package`
+    pub fn describe(self, package: &PackageSpec) -> String {
+        match self {
+            IssueKind::UnknownNamespace => {
+                format!("Unknown namespace {} of {package}", package.namespace)
+            }
+            IssueKind::UnknownPackage => format!(
+                "Package @{}/{} is not in the registry",
+                package.namespace, package.name
+            ),
+            IssueKind::RegistryUnavailable => {
+                format!("Cannot check {package}, the registry is unavailable")
+            }
+            IssueKind::YankedVersion => {
+                format!("{package} has been yanked from the registry")
+            }
+        }
+    }
+}
+
+type UpgraderBuilder = dyn Fn(&PackageSpec) -> Result<PackageUpgrader, IssueKind>;
+
+/// Looks up packages in the registry, once per package for the whole run
+pub struct Resolver {
+    builder: Box<UpgraderBuilder>,
+    constraints: Constraints,
+    cache: RefCell<HashMap<PackageSpec, Result<Rc<PackageUpgrader>, IssueKind>>>,
+    decisions: RefCell<HashMap<Query, Decision>>,
+    /// Items exported by the new versions found locally
+    exports: RefCell<HashMap<PackageSpec, Option<Rc<HashSet<String>>>>>,
+}
+
+impl Resolver {
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    pub fn registry() -> Self {
+        Self::new(PackageUpgrader::build)
+    }
+
+    fn new(builder: impl Fn(&PackageSpec) -> Result<PackageUpgrader, IssueKind> + 'static) -> Self {
+        Self {
+            builder: Box::new(builder),
+            constraints: Constraints::default(),
+            cache: RefCell::default(),
+            decisions: RefCell::default(),
+            exports: RefCell::default(),
+        }
+    }
+
+    /// Only consider the versions allowed by `constraints` for upgrades
+    pub fn constraints(mut self, constraints: Constraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    fn resolve(&self, package: &PackageSpec) -> Result<Rc<PackageUpgrader>, IssueKind> {
+        self.cache
+            .borrow_mut()
+            .entry(package.clone())
+            .or_insert_with(|| {
+                (self.builder)(package)
+                    .map(|upgrader| Rc::new(upgrader.restrict(&self.constraints)))
+            })
+            .clone()
+    }
+
+    /// What to do with the imports matching `query`, made by `decide` on the first query
+    fn decision(&self, query: Query, decide: impl FnOnce() -> Decision) -> Decision {
+        if let Some(decision) = self.decisions.borrow().get(&query) {
+            return decision.clone();
+        }
+        let decision = decide();
+        self.decisions.borrow_mut().insert(query, decision.clone());
+        decision
+    }
+
+    /// The items exported by `package`, if it is found under `roots`
+    fn exports(&self, roots: &[PathBuf], package: &PackageSpec) -> Option<Rc<HashSet<String>>> {
+        self.exports
+            .borrow_mut()
+            .entry(package.clone())
+            .or_insert_with(|| {
+                package::find_package_dir(roots, package)
+                    .and_then(|dir| exports::package_exports(&dir))
+                    .map(Rc::new)
+            })
+            .clone()
+    }
+}
+
+/// Everything a decision about the imports of a package version depends on
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Query {
+    package: PackageSpec,
+    compatible: bool,
+    fix_yanked: bool,
+    rename: Option<Rename>,
+}
+
+/// What to do with the imports of a package version, made once per run
+#[derive(Debug, Clone)]
+enum Decision {
+    /// Leave the imports as they are
+    Keep,
+    /// The imports cannot be checked, or are stuck on a yanked version
+    Issue(PackageSpec, IssueKind),
+    /// Only an incompatible version is available
+    HeldBack(PackageSpec),
+    /// Rewrite the imports to `to`, noting the newer incompatible version held back if any
+    Upgrade {
+        to: PackageSpec,
+        held_back: Option<PackageSpec>,
+    },
+}
+
 pub struct TypstNodeUpgrader<'a> {
     root: &'a SyntaxNode,
-    verbose: bool,
+    resolver: &'a Resolver,
     compatible: bool,
-    upgrader_builder: Box<dyn Fn(&PackageSpec) -> PackageUpgrader>,
+    package_roots: &'a [PathBuf],
+    strict_imports: bool,
+    fix_yanked: bool,
+    renames: Option<&'a Renames>,
 }
 
 impl<'a> TypstNodeUpgrader<'a> {
-    #[cfg_attr(coverage_nightly, coverage(off))]
-    pub fn new(root: &'a SyntaxNode, verbose: bool, compatible: bool) -> Self {
-        Self::new_with_upgrader_builder(root, verbose, compatible, PackageUpgrader::build)
-    }
-
-    fn new_with_upgrader_builder(
-        root: &'a SyntaxNode,
-        verbose: bool,
-        compatible: bool,
-        upgrader_builder: impl Fn(&PackageSpec) -> PackageUpgrader + 'static,
-    ) -> Self {
+    pub fn new(root: &'a SyntaxNode, resolver: &'a Resolver, compatible: bool) -> Self {
         Self {
             root,
-            verbose,
+            resolver,
             compatible,
-            upgrader_builder: Box::new(upgrader_builder),
+            package_roots: &[],
+            strict_imports: false,
+            fix_yanked: false,
+            renames: None,
         }
     }
 
+    /// Replace renamed packages and warn about deprecated ones according to `renames`
+    pub fn renames(mut self, renames: &'a Renames) -> Self {
+        self.renames = Some(renames);
+        self
+    }
+
+    /// Move imports of yanked versions without upgrades to the nearest compatible version
+    pub fn fix_yanked(mut self, fix: bool) -> Self {
+        self.fix_yanked = fix;
+        self
+    }
+
+    /// Check imported items against the exports of new versions found under `roots`
+    ///
+    /// With `strict`, upgrades that would leave an imported item unresolved are refused.
+    pub fn check_imports(mut self, roots: &'a [PathBuf], strict: bool) -> Self {
+        self.package_roots = roots;
+        self.strict_imports = strict;
+        self
+    }
+
     /// Convert the whole syntax tree with the upgrader
     ///
-    /// Returns the converted node and whether there are incompatible versions
-    pub fn convert(&self) -> (SyntaxNode, bool) {
-        let mut has_incompat_version = false;
+    /// Returns the converted node and a report of the applied upgrades
+    pub fn convert(&self) -> (SyntaxNode, Report) {
+        let mut report = Report::default();
         let result = match self.root.kind() {
-            SyntaxKind::Markup => self.convert_recursively(self.root, &mut has_incompat_version),
+            SyntaxKind::Markup => self.convert_recursively(self.root, 0, &mut report),
             kind => panic!("Unexpected node kind: {kind:?}"),
         };
-        (result, has_incompat_version)
+        (result, report)
     }
 
     fn convert_recursively(
         &self,
         node: &SyntaxNode,
-        has_incompat_versions: &mut bool,
+        offset: usize,
+        report: &mut Report,
     ) -> SyntaxNode {
         if let Some(module_import) = node.cast::<ModuleImport>() {
             let Expr::Str(s) = module_import.source() else {
-                if self.verbose {
-                    info!(
-                        "NOTE": "Cannot upgrade non-string module import: {}",
-                        node.clone().into_text(),
-                    );
-                }
+                verbose!(
+                    "NOTE": "Cannot upgrade non-string module import: {}",
+                    node.clone().into_text(),
+                );
                 return node.clone();
             };
             let Ok(package) = PackageSpec::from_str(&s.get()) else {
+                debug!("Skipped": "Module import {:?} is not a package", s.get());
                 return node.clone();
             };
             if package.namespace == "local" {
-                if self.verbose {
-                    info!("NOTE": "Local package {package} is not upgradable");
-                }
+                verbose!("NOTE": "Local package {package} is not upgradable");
                 return node.clone();
             }
-            let next = if self.compatible {
-                match (
-                    (self.upgrader_builder)(&package).next(false),
-                    (self.upgrader_builder)(&package).next(true),
-                ) {
-                    (Some(incompat), Some(compat)) => {
-                        warn!("Update": "{package} -> {} (available: {})", compat.version, incompat.version);
-                        *has_incompat_versions = true;
-                        compat
-                    }
-                    (None, Some(compat)) => {
-                        if self.verbose {
-                            info!("Update": "{package}");
-                        }
-                        compat
-                    }
-                    (Some(incompat), None) => {
-                        if self.verbose {
-                            info!("NOTE": "Package {package} is already up-to-date");
-                        }
-                        warn!("Unchanged": "{package} (available: {})", incompat.version);
-                        *has_incompat_versions = true;
-                        return node.clone();
-                    }
-                    _ => {
-                        if self.verbose {
-                            info!("NOTE": "Package {package} is already up-to-date");
-                        }
-                        return node.clone();
-                    }
+            let range = Self::source_range(node, offset, module_import);
+            let query = Query {
+                package: package.clone(),
+                compatible: self.compatible,
+                fix_yanked: self.fix_yanked,
+                rename: self
+                    .renames
+                    .and_then(|renames| renames.rename(&package.name))
+                    .cloned(),
+            };
+            let next = match self.resolver.decision(query, || self.decide(&package)) {
+                Decision::Keep => return node.clone(),
+                Decision::Issue(package, kind) => {
+                    report.issues.push(Issue {
+                        range,
+                        package,
+                        kind,
+                    });
+                    return node.clone();
                 }
-            } else if let Some(next) = (self.upgrader_builder)(&package).next(false) {
-                if self.verbose {
-                    info!("Update": "{package} -> {}", next.version);
+                Decision::HeldBack(to) => {
+                    report.has_incompat_versions = true;
+                    report.held_back.push(Upgrade {
+                        range,
+                        from: package,
+                        to,
+                    });
+                    return node.clone();
                 }
-                next
-            } else {
-                if self.verbose {
-                    info!("NOTE": "Package {package} is already up-to-date");
+                Decision::Upgrade { to, held_back } => {
+                    if let Some(held_back) = held_back {
+                        report.has_incompat_versions = true;
+                        report.held_back.push(Upgrade {
+                            range: range.clone(),
+                            from: package.clone(),
+                            to: held_back,
+                        });
+                    }
+                    to
                 }
-                return node.clone();
             };
+            if !self.check_imported_items(module_import, &next) {
+                warn!("Refused": "{package} -> {} would break imported items", next.version);
+                report.refused.push(Upgrade {
+                    range,
+                    from: package,
+                    to: next,
+                });
+                return node.clone();
+            }
+            // A bare import binds the package name, so keep binding the old one on renames
+            let bind_as = (next.name != package.name
+                && module_import.imports().is_none()
+                && module_import.new_name().is_none())
+            .then(|| package.name.clone());
+            report.upgrades.push(Upgrade {
+                range,
+                from: package,
+                to: next.clone(),
+            });
+            let mut offset = offset;
             SyntaxNode::inner(
                 node.kind(),
                 node.children()
-                    .map(|child| match child.kind() {
-                        SyntaxKind::Str
-                            if child.text() == module_import.source().to_untyped().text() =>
-                        {
-                            SyntaxNode::leaf(SyntaxKind::Str, format!("\"{next}\""))
+                    .flat_map(|child| {
+                        let start = offset;
+                        offset += child.len();
+                        match child.kind() {
+                            SyntaxKind::Str
+                                if child.text() == module_import.source().to_untyped().text() =>
+                            {
+                                let mut nodes =
+                                    vec![SyntaxNode::leaf(SyntaxKind::Str, format!("\"{next}\""))];
+                                if let Some(name) = &bind_as {
+                                    nodes.extend([
+                                        SyntaxNode::leaf(SyntaxKind::Space, " "),
+                                        SyntaxNode::leaf(SyntaxKind::As, "as"),
+                                        SyntaxNode::leaf(SyntaxKind::Space, " "),
+                                        SyntaxNode::leaf(SyntaxKind::Ident, name.as_str()),
+                                    ]);
+                                }
+                                nodes
+                            }
+                            _ => vec![self.convert_recursively(child, start, report)],
                         }
-                        _ => self.convert_recursively(child, has_incompat_versions),
                     })
                     .collect(),
             )
         } else if node.children().len() == 0 {
             node.clone()
         } else {
+            let mut offset = offset;
             SyntaxNode::inner(
                 node.kind(),
                 node.children()
-                    .map(|child| self.convert_recursively(child, has_incompat_versions))
+                    .map(|child| {
+                        let start = offset;
+                        offset += child.len();
+                        self.convert_recursively(child, start, report)
+                    })
                     .collect(),
             )
         }
     }
+
+    /// Decide what to do with the imports of `package`, printing why
+    fn decide(&self, package: &PackageSpec) -> Decision {
+        match self
+            .renames
+            .and_then(|renames| renames.deprecation(&package.name))
+        {
+            Some("") => {
+                warn!("Deprecated": "{package}");
+            }
+            Some(message) => {
+                warn!("Deprecated": "{package}: {message}");
+            }
+            None => (),
+        }
+        if let Some(rename) = self
+            .renames
+            .and_then(|renames| renames.rename(&package.name))
+        {
+            let replacement = PackageSpec {
+                name: rename.to.as_str().into(),
+                version: PackageVersion {
+                    major: 0,
+                    minor: 0,
+                    patch: 0,
+                },
+                ..package.clone()
+            };
+            let upgrader = match self.upgrader(&replacement) {
+                Ok(upgrader) => upgrader,
+                Err(decision) => return decision,
+            };
+            let Some(next) = upgrader.latest_since(rename.from) else {
+                warn!(
+                    "Unchanged": "{package} (renamed to {}, but no suitable version is available)",
+                    rename.to,
+                );
+                return Decision::Keep;
+            };
+            info!("Replaced": "{package} -> {next}");
+            return Decision::Upgrade {
+                to: next,
+                held_back: None,
+            };
+        }
+
+        let upgrader = match self.upgrader(package) {
+            Ok(upgrader) => upgrader,
+            Err(decision) => return decision,
+        };
+        let stuck = upgrader.next(self.compatible).is_none();
+        if upgrader.yanked && !stuck {
+            warn!("Yanked": "{package} is no longer in the registry");
+        }
+        let upgrade = |to| Decision::Upgrade {
+            to,
+            held_back: None,
+        };
+        if upgrader.yanked && stuck {
+            let Some(fallback) = upgrader.fallback().filter(|_| self.fix_yanked) else {
+                let kind = IssueKind::YankedVersion;
+                error!("Yanked": "{}", kind.describe(package));
+                return Decision::Issue(package.clone(), kind);
+            };
+            warn!("Yanked": "{package} -> {} (nearest available version)", fallback.version);
+            upgrade(fallback)
+        } else if self.compatible {
+            match (upgrader.next(false), upgrader.next(true)) {
+                (Some(incompat), Some(compat)) if incompat != compat => {
+                    warn!("Update": "{package} -> {} (available: {})", compat.version, incompat.version);
+                    Decision::Upgrade {
+                        to: compat,
+                        held_back: Some(incompat),
+                    }
+                }
+                (_, Some(compat)) => {
+                    verbose!("Update": "{package}");
+                    upgrade(compat)
+                }
+                (Some(incompat), None) => {
+                    verbose!("NOTE": "Package {package} is already up-to-date");
+                    warn!("Unchanged": "{package} (available: {})", incompat.version);
+                    Decision::HeldBack(incompat)
+                }
+                _ => {
+                    verbose!("NOTE": "Package {package} is already up-to-date");
+                    Decision::Keep
+                }
+            }
+        } else {
+            let Some(next) = upgrader.next(false) else {
+                verbose!("NOTE": "Package {package} is already up-to-date");
+                return Decision::Keep;
+            };
+            verbose!("Update": "{package} -> {}", next.version);
+            upgrade(next)
+        }
+    }
+
+    /// Look up the versions of `package`, or the decision to report it as unchecked
+    fn upgrader(&self, package: &PackageSpec) -> Result<Rc<PackageUpgrader>, Decision> {
+        self.resolver.resolve(package).map_err(|kind| {
+            error!("Unchecked": "{}", kind.describe(package));
+            Decision::Issue(package.clone(), kind)
+        })
+    }
+
+    /// Byte range of the source string of `module_import`, whose node starts at `offset`
+    fn source_range(node: &SyntaxNode, offset: usize, module_import: ModuleImport) -> Range<usize> {
+        let source = module_import.source().to_untyped();
+        let mut start = offset;
+        for child in node.children() {
+            if child.kind() == SyntaxKind::Str && child.text() == source.text() {
+                return start..start + child.len();
+            }
+            start += child.len();
+        }
+        unreachable!("The source string of a module import is its child")
+    }
+
+    /// Warn about imported items that `next` no longer exports, if it is available locally
+    ///
+    /// Returns whether the upgrade may be applied.
+    fn check_imported_items(&self, module_import: ModuleImport, next: &PackageSpec) -> bool {
+        let Some(Imports::Items(items)) = module_import.imports() else {
+            return true;
+        };
+        let Some(exports) = self.resolver.exports(self.package_roots, next) else {
+            return true;
+        };
+
+        let mut resolvable = true;
+        for name in items.iter().filter_map(|item| item.path().iter().next()) {
+            if !exports.contains(name.get().as_str()) {
+                warn!("Missing": "`{}` is not exported by {next}", name.get());
+                resolvable = false;
+            }
+        }
+
+        resolvable || !self.strict_imports
+    }
 }
 
 struct PackageUpgrader {
     pkg: PackageSpec,
     ver: Vec<PackageSpec>,
+    /// Whether the version of `pkg` is no longer in the registry
+    yanked: bool,
+    /// Older versions compatible with a yanked `pkg`
+    fallbacks: Vec<PackageSpec>,
+    /// Publication times of the versions, in seconds since the Unix epoch, if known
+    released: HashMap<PackageVersion, u64>,
 }
 
 impl PackageUpgrader {
     #[cfg_attr(coverage_nightly, coverage(off))]
-    fn build(package: &PackageSpec) -> Self {
-        static TYPST_PACKAGE_META: Lazy<HashMap<String, Vec<PackageVersion>>> = Lazy::new(|| {
-            let mut retry_count = 5;
-            let resp = loop {
-                let now = Instant::now();
-                match reqwest::blocking::get("https://packages.typst.org/preview/index.json") {
-                    Ok(resp) => {
-                        let elapsed = now.elapsed();
-                        if elapsed >= Duration::from_secs(1) {
-                            warn!(
-                                "Network": "Fetched typst package metadata in {}.{:03}s",
-                                elapsed.as_secs(),
-                                elapsed.subsec_millis(),
-                            );
-                        }
-                        break resp;
-                    }
-                    Err(_) if retry_count > 0 => {
-                        retry_count -= 1;
-                        warn!(
-                            "Network": "Failed to fetch package metadata, retrying... ({} attempts left)",
-                            retry_count,
-                        );
-                    }
-                    Err(_) => panic!("Failed to fetch package metadata"),
-                }
-            };
-            let raw_meta = resp
-                .json::<serde_json::Value>()
-                .expect("Failed to parse package metadata")
-                .as_array()
-                .expect("Invalid package metadata")
-                .iter()
-                .map(|v| {
-                    let package = v.as_object().expect("Invalid package metadata");
-                    let name = package
-                        .get("name")
-                        .expect("Package name not found")
-                        .as_str()
-                        .unwrap();
-                    let version = PackageVersion::from_str(
-                        package
-                            .get("version")
-                            .expect("Package version not found")
-                            .as_str()
-                            .unwrap(),
-                    )
-                    .unwrap();
-                    (name.to_string(), version)
-                })
-                .collect::<Vec<_>>();
-
-            let mut result = HashMap::new();
-            for (name, version) in raw_meta {
-                result.entry(name).or_insert_with(Vec::new).push(version);
-            }
-
-            result
-        });
-
-        Self::build_with_query(package, |name| TYPST_PACKAGE_META.get(name).cloned())
+    fn build(package: &PackageSpec) -> Result<Self, IssueKind> {
+        Self::check_namespace(package)?;
+        let index = index::registry_index().ok_or(IssueKind::RegistryUnavailable)?;
+        Self::build_with_query(package, |name| index.releases(name))
     }
 
-    fn build_with_query<Q, R>(package: &PackageSpec, query: Q) -> Self
-    where
-        Q: Fn(&str) -> Option<R>,
-        R: IntoIterator<Item = PackageVersion>,
-    {
+    fn check_namespace(package: &PackageSpec) -> Result<(), IssueKind> {
         if package.namespace == "local" {
             panic!("Local package {package} is not upgradable");
         }
 
         if package.namespace != "preview" {
-            panic!(
-                "Unknown namespace {} for package {}",
-                package.namespace, package
-            );
+            return Err(IssueKind::UnknownNamespace);
         }
 
-        let ver: Vec<_> = (query)(&package.name)
-            .expect("Package not found")
+        Ok(())
+    }
+
+    fn build_with_query<Q, R>(package: &PackageSpec, query: Q) -> Result<Self, IssueKind>
+    where
+        Q: Fn(&str) -> Option<R>,
+        R: IntoIterator<Item = (PackageVersion, Option<u64>)>,
+    {
+        Self::check_namespace(package)?;
+
+        let mut released = HashMap::new();
+        let versions: Vec<_> = (query)(&package.name)
+            .ok_or(IssueKind::UnknownPackage)?
             .into_iter()
-            .filter(|version| *version > package.version)
-            .map(|version| PackageSpec {
-                version,
-                ..package.clone()
+            .map(|(version, updated_at)| {
+                if let Some(updated_at) = updated_at {
+                    released.insert(version, updated_at);
+                }
+                version
             })
             .collect();
+        let spec = |version| PackageSpec {
+            version,
+            ..package.clone()
+        };
+        let yanked = !versions.contains(&package.version);
+        let fallbacks = versions
+            .iter()
+            .filter(|version| {
+                yanked && **version < package.version && Self::caret(package.version, **version)
+            })
+            .map(|version| spec(*version))
+            .collect();
+        let ver = versions
+            .into_iter()
+            .filter(|version| *version > package.version)
+            .map(spec)
+            .collect();
 
-        PackageUpgrader {
+        Ok(PackageUpgrader {
             pkg: package.clone(),
             ver,
+            yanked,
+            fallbacks,
+            released,
+        })
+    }
+
+    /// Whether `other` is compatible with `version` by the rules of semver caret requirements
+    ///
+    /// Unlike upgrades, which need a stable major version, this also accepts `0.x` versions
+    /// with the same minor version.
+    fn caret(version: PackageVersion, other: PackageVersion) -> bool {
+        match version.major {
+            0 => other.major == 0 && other.minor == version.minor,
+            major => other.major == major,
         }
     }
 
+    /// Drop the versions not allowed by `constraints`, e.g. those published too recently
+    fn restrict(mut self, constraints: &Constraints) -> Self {
+        let released = &self.released;
+        let allows =
+            |spec: &PackageSpec| constraints.allows(spec, released.get(&spec.version).copied());
+        self.ver.retain(allows);
+        self.fallbacks.retain(allows);
+        self
+    }
+
+    /// The latest older version compatible with a yanked `pkg`
+    fn fallback(&self) -> Option<PackageSpec> {
+        self.fallbacks.iter().max_by_key(|dep| dep.version).cloned()
+    }
+
     fn next(&self, compatible: bool) -> Option<PackageSpec> {
         self.ver
             .iter()
@@ -254,18 +669,40 @@ impl PackageUpgrader {
             .max_by_key(|dep| dep.version)
             .cloned()
     }
+
+    /// The latest version regardless of compatibility, not older than `since`
+    fn latest_since(&self, since: Option<PackageVersion>) -> Option<PackageSpec> {
+        self.ver
+            .iter()
+            .filter(|dep| since.is_none_or(|since| dep.version >= since))
+            .max_by_key(|dep| dep.version)
+            .cloned()
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use std::{fs, str::FromStr};
+    use std::{
+        cell::Cell,
+        fs,
+        path::Path,
+        rc::Rc,
+        str::FromStr,
+        time::{Duration, UNIX_EPOCH},
+    };
 
     use paste::paste;
     use typst_syntax::package::{PackageSpec, PackageVersion};
 
-    use crate::upgrade::PackageUpgrader;
+    use regex::Regex;
 
-    use super::TypstNodeUpgrader;
+    use crate::{
+        constraint::{Age, Constraint, Constraints},
+        rename::Renames,
+        upgrade::PackageUpgrader,
+    };
+
+    use super::{Issue, IssueKind, Resolver, TypstNodeUpgrader, Upgrade};
 
     #[test]
     fn next() {
@@ -274,6 +711,9 @@ mod test {
         let upgrader = PackageUpgrader {
             pkg: package.clone(),
             ver: Vec::new(),
+            yanked: false,
+            fallbacks: Vec::new(),
+            released: Default::default(),
         };
         assert!(upgrader.next(true).is_none());
         assert!(upgrader.next(false).is_none());
@@ -281,6 +721,9 @@ mod test {
         let upgrader = PackageUpgrader {
             pkg: package.clone(),
             ver: vec![PackageSpec::from_str("@preview/package:2.0.0").unwrap()],
+            yanked: false,
+            fallbacks: Vec::new(),
+            released: Default::default(),
         };
         assert!(upgrader.next(true).is_none());
 
@@ -297,6 +740,9 @@ mod test {
                 PackageSpec::from_str("@preview/package:1.3.0").unwrap(),
                 PackageSpec::from_str("@preview/package:2.0.0").unwrap(),
             ],
+            yanked: false,
+            fallbacks: Vec::new(),
+            released: Default::default(),
         };
 
         let next_compat = upgrader.next(true);
@@ -313,11 +759,22 @@ mod test {
     }
 
     #[test]
-    #[should_panic]
     fn should_not_upgrade_non_preview() {
         let package = PackageSpec::from_str("@non-preview/package:1.2.3").unwrap();
         assert_eq!(package.namespace, "non-preview");
-        PackageUpgrader::build_with_query(&package, |_| -> Option<Vec<_>> { None });
+        assert_eq!(
+            PackageUpgrader::build_with_query(&package, |_| -> Option<Vec<_>> { None }).err(),
+            Some(IssueKind::UnknownNamespace)
+        );
+    }
+
+    #[test]
+    fn should_not_upgrade_unknown() {
+        let package = PackageSpec::from_str("@preview/unknown:1.2.3").unwrap();
+        assert_eq!(
+            PackageUpgrader::build_with_query(&package, mock_query).err(),
+            Some(IssueKind::UnknownPackage)
+        );
     }
 
     #[test]
@@ -325,13 +782,13 @@ mod test {
     fn should_not_upgrade_local() {
         let package = PackageSpec::from_str("@local/package:1.2.3").unwrap();
         assert!(package.namespace == "local");
-        PackageUpgrader::build_with_query(&package, |_| -> Option<Vec<_>> { None });
+        let _ = PackageUpgrader::build_with_query(&package, |_| -> Option<Vec<_>> { None });
     }
 
     #[test]
     fn upgrader_build() {
         let package = PackageSpec::from_str("@preview/pack1:1.1.0").unwrap();
-        let upgrader = PackageUpgrader::build_with_query(&package, mock_query);
+        let upgrader = PackageUpgrader::build_with_query(&package, mock_query).unwrap();
         assert_eq!(
             upgrader.next(true).unwrap().to_string(),
             "@preview/pack1:1.1.1"
@@ -342,11 +799,295 @@ mod test {
         );
     }
 
+    #[test]
+    fn resolver() {
+        let calls = Rc::new(Cell::new(0));
+        let resolver = Resolver::new({
+            let calls = calls.clone();
+            move |package| {
+                calls.set(calls.get() + 1);
+                mock_upgrader_builder(package)
+            }
+        });
+        let tree = typst_syntax::parse(
+            "#import \"@preview/pack1:1.1.0\"\n#import \"@preview/pack1:1.1.0\": a\n#import \"@preview/unknown:1.0.0\"\n",
+        );
+        for _ in 0..2 {
+            let (_, report) = TypstNodeUpgrader::new(&tree, &resolver, true).convert();
+            assert_eq!(report.upgrades.len(), 2);
+            assert_eq!(report.issues.len(), 1);
+        }
+        assert_eq!(calls.get(), 2);
+        assert_eq!(resolver.decisions.borrow().len(), 2);
+    }
+
+    #[test]
+    fn report_upgrades() {
+        let tree = typst_syntax::parse(
+            "#import \"@preview/pack1:1.1.0\"\n#import \"@preview/pack2:0.1.0\"\n",
+        );
+        let (_, report) = TypstNodeUpgrader::new(&tree, &mock_resolver(), true).convert();
+        assert!(report.has_incompat_versions);
+        assert_eq!(
+            report.held_back,
+            [
+                Upgrade {
+                    range: 8..30,
+                    from: PackageSpec::from_str("@preview/pack1:1.1.0").unwrap(),
+                    to: PackageSpec::from_str("@preview/pack1:2.0.0").unwrap(),
+                },
+                Upgrade {
+                    range: 39..61,
+                    from: PackageSpec::from_str("@preview/pack2:0.1.0").unwrap(),
+                    to: PackageSpec::from_str("@preview/pack2:2.0.0").unwrap(),
+                }
+            ]
+        );
+        assert_eq!(
+            report.upgrades,
+            [Upgrade {
+                range: 8..30,
+                from: PackageSpec::from_str("@preview/pack1:1.1.0").unwrap(),
+                to: PackageSpec::from_str("@preview/pack1:1.1.1").unwrap(),
+            }]
+        );
+    }
+
+    #[test]
+    fn report_issues() {
+        let tree = typst_syntax::parse(
+            "#import \"@other/pack1:1.0.0\"\n#import \"@preview/unknown:1.0.0\"\n",
+        );
+        let (result, report) = TypstNodeUpgrader::new(&tree, &mock_resolver(), true).convert();
+        assert_eq!(result, tree);
+        assert!(report.upgrades.is_empty());
+        assert_eq!(
+            report.issues,
+            [
+                Issue {
+                    range: 8..28,
+                    package: PackageSpec::from_str("@other/pack1:1.0.0").unwrap(),
+                    kind: IssueKind::UnknownNamespace,
+                },
+                Issue {
+                    range: 37..61,
+                    package: PackageSpec::from_str("@preview/unknown:1.0.0").unwrap(),
+                    kind: IssueKind::UnknownPackage,
+                }
+            ]
+        );
+        assert_eq!(
+            report.issues[0].describe(),
+            "Unknown namespace other of @other/pack1:1.0.0"
+        );
+        assert_eq!(
+            report.issues[1].describe(),
+            "Package @preview/unknown is not in the registry"
+        );
+    }
+
+    #[test]
+    fn check_imports() {
+        let tree = typst_syntax::parse("#import \"@preview/pack1:1.1.0\": pack, helper\n");
+        let roots = vec![
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests")
+                .join("packages"),
+        ];
+
+        let (result, report) = TypstNodeUpgrader::new(&tree, &mock_resolver(), false)
+            .check_imports(&roots, false)
+            .convert();
+        assert_eq!(
+            result.into_text(),
+            "#import \"@preview/pack1:2.0.0\": pack, helper\n"
+        );
+        assert_eq!(report.upgrades.len(), 1);
+
+        let (result, report) = TypstNodeUpgrader::new(&tree, &mock_resolver(), false)
+            .check_imports(&roots, true)
+            .convert();
+        assert_eq!(result, tree);
+        assert!(report.upgrades.is_empty());
+        assert_eq!(
+            report.refused,
+            [Upgrade {
+                range: 8..30,
+                from: PackageSpec::from_str("@preview/pack1:1.1.0").unwrap(),
+                to: PackageSpec::from_str("@preview/pack1:2.0.0").unwrap(),
+            }]
+        );
+
+        // The exports are read once, but the items of each import are checked
+        let tree = typst_syntax::parse(
+            "#import \"@preview/pack1:1.1.0\": pack\n#import \"@preview/pack1:1.1.0\": helper\n",
+        );
+        let resolver = mock_resolver();
+        let (_, report) = TypstNodeUpgrader::new(&tree, &resolver, false)
+            .check_imports(&roots, true)
+            .convert();
+        assert_eq!(report.refused.len(), 1);
+        assert_eq!(report.upgrades.len(), 1);
+        assert_eq!(resolver.exports.borrow().len(), 1);
+    }
+
+    #[test]
+    fn renames() {
+        let mut renames = Renames::default();
+        renames
+            .extend_from_str(
+                "pack2 -> pack3 from 1.0.0\npack4 -> pack3 from 4.0.0\npack1 deprecated",
+            )
+            .unwrap();
+
+        let tree = typst_syntax::parse(
+            "#import \"@preview/pack1:0.1.0\"\n#import \"@preview/pack2:0.1.0\": pack\n#import \"@preview/pack4:1.0.0\"\n#import \"@preview/pack2:0.1.0\"\n#pack2.pack\n",
+        );
+        let (result, report) = TypstNodeUpgrader::new(&tree, &mock_resolver(), true)
+            .renames(&renames)
+            .convert();
+        assert_eq!(
+            result.into_text(),
+            "#import \"@preview/pack1:0.1.0\"\n#import \"@preview/pack3:3.0.0\": pack\n#import \"@preview/pack4:1.0.0\"\n#import \"@preview/pack3:3.0.0\" as pack2\n#pack2.pack\n"
+        );
+        assert_eq!(
+            report.upgrades,
+            [
+                Upgrade {
+                    range: 39..61,
+                    from: PackageSpec::from_str("@preview/pack2:0.1.0").unwrap(),
+                    to: PackageSpec::from_str("@preview/pack3:3.0.0").unwrap(),
+                },
+                Upgrade {
+                    range: 107..129,
+                    from: PackageSpec::from_str("@preview/pack2:0.1.0").unwrap(),
+                    to: PackageSpec::from_str("@preview/pack3:3.0.0").unwrap(),
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn yanked() {
+        let tree = typst_syntax::parse(
+            "#import \"@preview/pack1:1.1.5\"\n#import \"@preview/pack2:0.1.5\"\n#import \"@preview/pack3:0.3.0\"\n",
+        );
+        let (result, report) = TypstNodeUpgrader::new(&tree, &mock_resolver(), true).convert();
+        assert_eq!(result, tree);
+        assert_eq!(
+            report
+                .issues
+                .iter()
+                .map(|issue| (issue.kind, issue.describe()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    IssueKind::YankedVersion,
+                    "@preview/pack1:1.1.5 has been yanked from the registry".to_string()
+                ),
+                (
+                    IssueKind::YankedVersion,
+                    "@preview/pack2:0.1.5 has been yanked from the registry".to_string()
+                ),
+                (
+                    IssueKind::YankedVersion,
+                    "@preview/pack3:0.3.0 has been yanked from the registry".to_string()
+                ),
+            ]
+        );
+
+        let (result, report) = TypstNodeUpgrader::new(&tree, &mock_resolver(), true)
+            .fix_yanked(true)
+            .convert();
+        assert_eq!(
+            result.into_text(),
+            "#import \"@preview/pack1:1.1.1\"\n#import \"@preview/pack2:0.1.0\"\n#import \"@preview/pack3:0.3.0\"\n"
+        );
+        assert_eq!(
+            report.upgrades[0].describe(),
+            "@preview/pack1 1.1.5 is yanked and can be downgraded to 1.1.1"
+        );
+        // No compatible version of pack3 is left
+        assert_eq!(report.issues.len(), 1);
+
+        // Upgrades move away from yanked versions anyway
+        let (result, report) = TypstNodeUpgrader::new(&tree, &mock_resolver(), false).convert();
+        assert_eq!(
+            result.into_text(),
+            "#import \"@preview/pack1:2.0.0\"\n#import \"@preview/pack2:2.0.0\"\n#import \"@preview/pack3:3.0.0\"\n"
+        );
+        assert!(report.issues.is_empty());
+    }
+
+    #[test]
+    fn constraints() {
+        let resolver = mock_resolver().constraints(Constraints::new(
+            vec![Regex::new(r"^1\.1\.1$").unwrap()],
+            vec![Constraint::from_str("pack2=<1.1").unwrap()],
+        ));
+        let tree = typst_syntax::parse(
+            "#import \"@preview/pack1:1.0.0\"\n#import \"@preview/pack2:0.1.0\"\n",
+        );
+        let (result, _) = TypstNodeUpgrader::new(&tree, &resolver, false).convert();
+        assert_eq!(
+            result.into_text(),
+            "#import \"@preview/pack1:2.0.0\"\n#import \"@preview/pack2:1.0.0\"\n"
+        );
+        let (result, _) = TypstNodeUpgrader::new(&tree, &resolver, true).convert();
+        assert_eq!(
+            result.into_text(),
+            "#import \"@preview/pack1:1.1.0\"\n#import \"@preview/pack2:0.1.0\"\n"
+        );
+    }
+
+    #[test]
+    fn min_age() {
+        let package = PackageSpec::from_str("@preview/pack1:1.0.0").unwrap();
+        let upgrader = PackageUpgrader::build_with_query(&package, |_| {
+            Some(vec![
+                (PackageVersion::from_str("1.0.0").unwrap(), Some(100)),
+                (PackageVersion::from_str("1.1.0").unwrap(), Some(200)),
+                (PackageVersion::from_str("1.2.0").unwrap(), Some(300)),
+            ])
+        })
+        .unwrap()
+        .restrict(&Constraints::default().min_age(
+            Age::from_str("1m").unwrap(),
+            UNIX_EPOCH + Duration::from_secs(290),
+        ));
+        assert_eq!(
+            upgrader.next(true).unwrap().to_string(),
+            "@preview/pack1:1.1.0"
+        );
+    }
+
+    #[test]
+    fn latest_since() {
+        let package = PackageSpec::from_str("@preview/pack3:0.0.0").unwrap();
+        let upgrader = PackageUpgrader::build_with_query(&package, mock_query).unwrap();
+        assert_eq!(
+            upgrader.latest_since(None).unwrap().to_string(),
+            "@preview/pack3:3.0.0"
+        );
+        assert_eq!(
+            upgrader
+                .latest_since(Some(PackageVersion::from_str("3.0.0").unwrap()))
+                .unwrap()
+                .to_string(),
+            "@preview/pack3:3.0.0"
+        );
+        assert!(
+            upgrader
+                .latest_since(Some(PackageVersion::from_str("3.0.1").unwrap()))
+                .is_none()
+        );
+    }
+
     #[test]
     #[should_panic]
     fn should_not_convert_illegal_root() {
         let root = typst_syntax::parse_math("$1 + 2$");
-        TypstNodeUpgrader::new(&root, false, true).convert();
+        TypstNodeUpgrader::new(&root, &mock_resolver(), true).convert();
     }
 
     macro_rules! ex_test {
@@ -363,11 +1104,10 @@ mod test {
                     )).unwrap();
 
                     let old_tree = typst_syntax::parse(&entry);
-                    let new_compat = TypstNodeUpgrader::new_with_upgrader_builder(
+                    let new_compat = TypstNodeUpgrader::new(
                         &old_tree,
-                        true,
-                        true,
-                        mock_upgrader_builder,
+                        &mock_resolver(),
+                        true
                     ).convert().0;
                     let res_compat = fs::read_to_string(&format!(
                         "{}/tests/{}/entry.compat.{}",
@@ -377,11 +1117,10 @@ mod test {
                     )).unwrap();
                     assert_eq!(new_compat.into_text(), res_compat, concat!("compat: ", stringify!($name), "/", $ext));
 
-                    let new_incompat = TypstNodeUpgrader::new_with_upgrader_builder(
+                    let new_incompat = TypstNodeUpgrader::new(
                         &old_tree,
-                        true,
-                        false,
-                        mock_upgrader_builder,
+                        &mock_resolver(),
+                        false
                     ).convert().0;
                     let res_incompat = fs::read_to_string(&format!(
                         "{}/tests/{}/entry.incompat.{}",
@@ -401,40 +1140,44 @@ mod test {
     ex_test! {
         normal1 / "typ",
         normal2 / "typst",
-        #[should_panic] exception1 / "typ",
+        exception1 / "typ",
         exception2 / "typ",
     }
 
-    fn mock_upgrader_builder(package: &PackageSpec) -> PackageUpgrader {
+    fn mock_resolver() -> Resolver {
+        Resolver::new(mock_upgrader_builder)
+    }
+
+    fn mock_upgrader_builder(package: &PackageSpec) -> Result<PackageUpgrader, IssueKind> {
         PackageUpgrader::build_with_query(package, mock_query)
     }
 
-    fn mock_query(name: &str) -> Option<Vec<PackageVersion>> {
+    fn mock_query(name: &str) -> Option<Vec<(PackageVersion, Option<u64>)>> {
         match name {
             "pack1" => Some(vec![
-                PackageVersion::from_str("0.1.0").unwrap(),
-                PackageVersion::from_str("0.1.1").unwrap(),
-                PackageVersion::from_str("0.2.0").unwrap(),
-                PackageVersion::from_str("0.2.1").unwrap(),
-                PackageVersion::from_str("0.2.2").unwrap(),
-                PackageVersion::from_str("1.0.0").unwrap(),
-                PackageVersion::from_str("1.0.1").unwrap(),
-                PackageVersion::from_str("1.1.0").unwrap(),
-                PackageVersion::from_str("1.1.1").unwrap(),
-                PackageVersion::from_str("2.0.0").unwrap(),
+                (PackageVersion::from_str("0.1.0").unwrap(), None),
+                (PackageVersion::from_str("0.1.1").unwrap(), None),
+                (PackageVersion::from_str("0.2.0").unwrap(), None),
+                (PackageVersion::from_str("0.2.1").unwrap(), None),
+                (PackageVersion::from_str("0.2.2").unwrap(), None),
+                (PackageVersion::from_str("1.0.0").unwrap(), None),
+                (PackageVersion::from_str("1.0.1").unwrap(), None),
+                (PackageVersion::from_str("1.1.0").unwrap(), None),
+                (PackageVersion::from_str("1.1.1").unwrap(), None),
+                (PackageVersion::from_str("2.0.0").unwrap(), None),
             ]),
             "pack2" => Some(vec![
-                PackageVersion::from_str("0.1.0").unwrap(),
-                PackageVersion::from_str("1.0.0").unwrap(),
-                PackageVersion::from_str("1.1.0").unwrap(),
-                PackageVersion::from_str("2.0.0").unwrap(),
+                (PackageVersion::from_str("0.1.0").unwrap(), None),
+                (PackageVersion::from_str("1.0.0").unwrap(), None),
+                (PackageVersion::from_str("1.1.0").unwrap(), None),
+                (PackageVersion::from_str("2.0.0").unwrap(), None),
             ]),
             "pack3" => Some(vec![
-                PackageVersion::from_str("0.1.0").unwrap(),
-                PackageVersion::from_str("0.2.0").unwrap(),
-                PackageVersion::from_str("1.0.0").unwrap(),
-                PackageVersion::from_str("2.0.0").unwrap(),
-                PackageVersion::from_str("3.0.0").unwrap(),
+                (PackageVersion::from_str("0.1.0").unwrap(), None),
+                (PackageVersion::from_str("0.2.0").unwrap(), None),
+                (PackageVersion::from_str("1.0.0").unwrap(), None),
+                (PackageVersion::from_str("2.0.0").unwrap(), None),
+                (PackageVersion::from_str("3.0.0").unwrap(), None),
             ]),
             _ => None,
         }
@@ -0,0 +1,239 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use typst_syntax::package::PackageSpec;
+
+use crate::upgrade::{IssueKind, Report};
+
+/// How long to wait for more events after a change, as editors often emit several per save
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Check `files` for outdated imports, then re-check Typst files under `entries` whenever
+/// they change, printing the outdated imports and issues not reported before
+///
+/// `check` should not print anything itself.
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub fn run(
+    entries: &[PathBuf],
+    files: &[PathBuf],
+    check: impl Fn(&Path) -> Option<Report>,
+) -> ExitCode {
+    let (_watcher, changes) = match watch_files(entries) {
+        Ok(watch) => watch,
+        Err(err) => {
+            error!("Watch": "Cannot watch files: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut tracker = Tracker::default();
+    for file in files {
+        recheck(&mut tracker, file, &check);
+    }
+    info!("Watching": "{} file(s) for changes", files.len());
+
+    while let Ok(path) = changes.recv() {
+        let mut changed = BTreeSet::from([path]);
+        while let Ok(path) = changes.recv_timeout(DEBOUNCE) {
+            changed.insert(path);
+        }
+        for path in changed {
+            if path.is_file() {
+                recheck(&mut tracker, &path, &check);
+            } else {
+                tracker.forget(&path);
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+fn recheck(tracker: &mut Tracker, path: &Path, check: impl Fn(&Path) -> Option<Report>) {
+    let Some(report) = check(path) else {
+        return;
+    };
+    let findings = report
+        .upgrades
+        .into_iter()
+        .chain(report.held_back)
+        .chain(report.refused)
+        .map(|upgrade| Finding::Outdated(upgrade.from, upgrade.to))
+        .chain(
+            report
+                .issues
+                .iter()
+                .map(|issue| Finding::Issue(issue.kind, issue.describe())),
+        )
+        .collect();
+    for finding in tracker.update(path, findings) {
+        match finding {
+            Finding::Outdated(..) => {
+                warn!("Outdated": "{}: {finding}", path.display());
+            }
+            Finding::Issue(IssueKind::YankedVersion, _) => {
+                error!("Yanked": "{}: {finding}", path.display());
+            }
+            Finding::Issue(..) => {
+                error!("Unchecked": "{}: {finding}", path.display());
+            }
+        }
+    }
+}
+
+/// Something to report about the imports of a file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Finding {
+    /// An import of a package with a newer version
+    Outdated(PackageSpec, PackageSpec),
+    /// An import that cannot be checked, with its description
+    Issue(IssueKind, String),
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Outdated(from, to) => write!(f, "{from} -> {}", to.version),
+            Self::Issue(_, description) => description.fmt(f),
+        }
+    }
+}
+
+/// Watch `entries` recursively, receiving the paths of changed Typst files
+fn watch_files(entries: &[PathBuf]) -> notify::Result<(RecommendedWatcher, Receiver<PathBuf>)> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            return;
+        }
+        for path in event.paths {
+            if path
+                .extension()
+                .is_some_and(|ext| ext == "typ" || ext == "typst")
+            {
+                // The receiver is only gone when watching stops
+                let _ = sender.send(path);
+            }
+        }
+    })?;
+    for entry in entries {
+        watcher.watch(entry, RecursiveMode::Recursive)?;
+    }
+    Ok((watcher, receiver))
+}
+
+/// Remembers the findings of each file to report only newly introduced ones
+#[derive(Default)]
+struct Tracker {
+    known: HashMap<PathBuf, HashSet<Finding>>,
+}
+
+impl Tracker {
+    /// Record the findings of `path`, returning those not known before
+    fn update(&mut self, path: &Path, findings: HashSet<Finding>) -> Vec<Finding> {
+        let known = self.known.entry(Self::key(path)).or_default();
+        let mut introduced = findings.difference(known).cloned().collect::<Vec<_>>();
+        introduced.sort_by_key(Finding::to_string);
+        *known = findings;
+        introduced
+    }
+
+    fn forget(&mut self, path: &Path) {
+        self.known.remove(&Self::key(path));
+    }
+
+    /// Files are reported by relative and absolute paths, so compare them canonically
+    fn key(path: &Path) -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, fs, path::Path, str::FromStr, time::Duration};
+
+    use typst_syntax::package::PackageSpec;
+
+    use crate::upgrade::IssueKind;
+
+    use super::{Finding, Tracker};
+
+    fn outdated(pairs: &[(&str, &str)]) -> HashSet<Finding> {
+        pairs
+            .iter()
+            .map(|(from, to)| {
+                Finding::Outdated(
+                    PackageSpec::from_str(from).unwrap(),
+                    PackageSpec::from_str(to).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tracker() {
+        let mut tracker = Tracker::default();
+        let path = Path::new("main.typ");
+
+        let pack1 = ("@preview/pack1:1.0.0", "@preview/pack1:2.0.0");
+        let pack2 = ("@preview/pack2:1.0.0", "@preview/pack2:2.0.0");
+        assert_eq!(
+            tracker.update(path, outdated(&[pack1])),
+            Vec::from_iter(outdated(&[pack1]))
+        );
+        assert!(tracker.update(path, outdated(&[pack1])).is_empty());
+        assert_eq!(
+            tracker.update(path, outdated(&[pack1, pack2])),
+            Vec::from_iter(outdated(&[pack2]))
+        );
+        assert!(tracker.update(path, outdated(&[])).is_empty());
+        assert_eq!(
+            tracker.update(path, outdated(&[pack2])),
+            Vec::from_iter(outdated(&[pack2]))
+        );
+
+        tracker.forget(path);
+        assert_eq!(
+            tracker.update(path, outdated(&[pack2])),
+            Vec::from_iter(outdated(&[pack2]))
+        );
+
+        let issue = Finding::Issue(
+            IssueKind::UnknownPackage,
+            "Package @preview/pack3 is not in the registry".into(),
+        );
+        let mut findings = outdated(&[pack2]);
+        findings.insert(issue.clone());
+        assert_eq!(tracker.update(path, findings.clone()), [issue]);
+        assert!(tracker.update(path, findings).is_empty());
+    }
+
+    #[test]
+    fn watch_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("lib")).unwrap();
+        let (_watcher, changes) = super::watch_files(&[dir.path().to_path_buf()]).unwrap();
+
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        fs::write(dir.path().join("lib").join("main.typ"), "").unwrap();
+
+        let path = changes.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(path.file_name().unwrap(), "main.typ");
+        assert!(path.parent().unwrap().ends_with("lib"));
+    }
+}
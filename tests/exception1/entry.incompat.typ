@@ -0,0 +1,3 @@
+#import non-string: *
+#import "module1.typ"
+#import "@non-preview/package:1.0.0"
@@ -0,0 +1,10 @@
+#import "src/utils.typ": *
+#import "src/draw.typ"
+#import "src/shapes.typ": circle as round, rect
+
+#let pack-v2 = [pack]
+#let (major, minor) = (2, 0)
+
+#{
+  let hidden = none
+}
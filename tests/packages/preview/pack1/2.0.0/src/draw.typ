@@ -0,0 +1 @@
+#let line = none
@@ -0,0 +1,3 @@
+#let circle = none
+#let rect = none
+#let square = none
@@ -0,0 +1,3 @@
+#import "/lib.typ": pack-v2
+
+#let helper(x) = x